@@ -2,8 +2,8 @@ use proc_macro::Span;
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote, quote_spanned};
 use syn::{
-    parse_macro_input, parse_str, spanned::Spanned, Attribute, Data, DeriveInput, Expr, Fields,
-    GenericParam, Lit, LitByteStr, LitInt, Meta, Path,
+    parse_macro_input, parse_str, spanned::Spanned, Attribute, Data, DeriveInput, Expr, ExprLit,
+    ExprUnary, Fields, GenericParam, Lit, LitByteStr, LitInt, LitStr, Meta, Path, Token, UnOp,
 };
 
 fn meta_ident_eq(path: &Path, value: &str) -> bool {
@@ -25,12 +25,59 @@ fn parse_expr_in_str_literal(literal: &Lit) -> Option<TokenStream> {
     })
 }
 
+// Evaluates an integer literal, possibly negated, as used for explicit enum discriminants.
+fn parse_int_expr(expr: &Expr) -> Option<i128> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(lit), ..
+        }) => lit.base10_parse().ok(),
+        Expr::Unary(ExprUnary {
+            op: UnOp::Neg(_),
+            expr,
+            ..
+        }) => parse_int_expr(expr)?.checked_neg(),
+        Expr::Paren(paren) => parse_int_expr(&paren.expr),
+        Expr::Group(group) => parse_int_expr(&group.expr),
+        _ => None,
+    }
+}
+
+fn parse_variant_tag(attrs: &[Attribute]) -> syn::parse::Result<Option<i128>> {
+    let mut tag = None;
+
+    for attr in attrs {
+        let meta_list = match &attr.meta {
+            Meta::List(meta_list) => meta_list,
+            _ => continue,
+        };
+
+        if meta_ident_eq(&meta_list.path, "savestate") {
+            meta_list.parse_nested_meta(|nested_meta| {
+                if meta_ident_eq(&nested_meta.path, "tag") {
+                    tag = Some(
+                        parse_int_expr(&nested_meta.value()?.parse::<Expr>()?)
+                            .ok_or(nested_meta.error("invalid tag specification"))?,
+                    );
+                    Ok(())
+                } else {
+                    Err(nested_meta.error("invalid `savestate` attribute"))
+                }
+            })?;
+        }
+    }
+
+    Ok(tag)
+}
+
 #[derive(Default)]
 struct LoadStoreOptions {
     pre_store: Option<TokenStream>,
     post_store: Option<TokenStream>,
     post_load: Option<TokenStream>,
     only_load_in_place: bool,
+    by_name: bool,
+    version: Option<u32>,
+    migrations: Vec<(u32, TokenStream)>,
 }
 
 impl LoadStoreOptions {
@@ -47,7 +94,7 @@ impl LoadStoreOptions {
                 (
                     $name: literal,
                     $(($pre_post: literal, $fn_ident: ident)),*
-                    $(; $only_load_in_place: literal)?
+                    $(; $only_load_in_place: literal, $migrate_prefix: literal)?
                 ) => {
                     meta_list.parse_nested_meta(|nested_meta| {
                         $(if meta_ident_eq(&nested_meta.path, $pre_post) {
@@ -66,6 +113,17 @@ impl LoadStoreOptions {
                         $(if meta_ident_eq(&nested_meta.path, $only_load_in_place) {
                             options.only_load_in_place = true;
                             return Ok(());
+                        } else if let Some(from) = nested_meta.path.get_ident().and_then(|ident| {
+                            ident.to_string().strip_prefix($migrate_prefix)?.parse::<u32>().ok()
+                        }) {
+                            options.migrations.push((
+                                from,
+                                parse_expr_in_str_literal(&nested_meta.value()?.parse::<Lit>()?)
+                                    .ok_or(
+                                        nested_meta.error("invalid migration code specification"),
+                                    )?,
+                            ));
+                            return Ok(());
                         })*
                         return Err(nested_meta.error(concat!("invalid `", $name, "` attribute")));
                     })?;
@@ -75,7 +133,33 @@ impl LoadStoreOptions {
             if meta_ident_eq(&meta_list.path, "store") {
                 parse_fns!("store", ("pre", pre_store), ("post", post_store));
             } else if meta_ident_eq(&meta_list.path, "load") {
-                parse_fns!("load", ("post", post_load); "in_place_only");
+                parse_fns!("load", ("post", post_load); "in_place_only", "migrate_from_");
+            } else if meta_ident_eq(&meta_list.path, "savestate") {
+                meta_list.parse_nested_meta(|nested_meta| {
+                    if meta_ident_eq(&nested_meta.path, "by_name") {
+                        options.by_name = true;
+                        Ok(())
+                    } else if meta_ident_eq(&nested_meta.path, "version") {
+                        options.version =
+                            Some(nested_meta.value()?.parse::<LitInt>()?.base10_parse()?);
+                        Ok(())
+                    } else {
+                        Err(nested_meta.error("invalid `savestate` attribute"))
+                    }
+                })?;
+            }
+        }
+
+        // Migrations are applied in order, each converting from its version to the next one
+        options.migrations.sort_by_key(|(from, _)| *from);
+        for (i, (from, _)) in options.migrations.iter().enumerate() {
+            match options.version {
+                Some(version) if *from < version => {}
+                Some(_) => panic!("can't migrate from version {from}, which isn't an older one"),
+                None => panic!("migrations can only be specified for versioned types"),
+            }
+            if i != 0 && options.migrations[i - 1].0 == *from {
+                panic!("multiple migrations from version {from}");
             }
         }
 
@@ -133,10 +217,12 @@ impl FieldsData {
         let mut load_in_place = Vec::new();
         let mut store = Vec::new();
 
-        for (name, ident, field) in fields_and_idents {
+        for (mut name, ident, field) in fields_and_idents {
             let mut load_kind = Some(LoadStoreKind::Default);
             let mut load_in_place_kind = Some(LoadStoreKind::Default);
             let mut store_kind = Some(LoadStoreKind::Default);
+            let mut aliases = Vec::new();
+            let mut default_value = None;
 
             for attr in &field.attrs {
                 let meta_list = match &attr.meta {
@@ -204,6 +290,22 @@ impl FieldsData {
                                             #expr
                                         }));
                                     return Ok(());
+                                } else if meta_ident_eq(&nested_meta.path, "default") {
+                                    default_value = Some(if nested_meta.input.peek(Token![=]) {
+                                        parse_expr_in_str_literal(
+                                            &nested_meta.value()?.parse::<Lit>()?,
+                                        )
+                                        .ok_or(
+                                            nested_meta.error(concat!(
+                                                "invalid ",
+                                                $name,
+                                                " default value specification",
+                                            )),
+                                        )?
+                                    } else {
+                                        quote! { ::core::default::Default::default() }
+                                    });
+                                    return Ok(());
                                 }
                             )*
 
@@ -227,6 +329,21 @@ impl FieldsData {
                             load_in_place_kind = None;
                             store_kind = None;
                             Ok(())
+                        } else if meta_ident_eq(&nested_meta.path, "rename")
+                            || meta_ident_eq(&nested_meta.path, "alias")
+                        {
+                            if name.is_none() {
+                                return Err(nested_meta
+                                    .error("only named fields can be renamed or aliased"));
+                            }
+                            let lit = nested_meta.value()?.parse::<LitStr>()?;
+                            let lit = LitByteStr::new(lit.value().as_bytes(), lit.span());
+                            if meta_ident_eq(&nested_meta.path, "rename") {
+                                name = Some(lit);
+                            } else {
+                                aliases.push(lit);
+                            }
+                            Ok(())
                         } else {
                             Err(nested_meta.error(concat!("invalid `savestate` attribute")))
                         }
@@ -285,15 +402,43 @@ impl FieldsData {
                 });
             }
 
+            if default_value.is_some() && name.is_none() {
+                return Err(syn::Error::new(
+                    field.span(),
+                    "only named fields can have default values",
+                ));
+            }
+
             if let Some(load_kind) = load_kind {
+                // Fields with a default value are only loaded if they're present in the save
+                let default_expr = default_value.clone().unwrap_or_default();
+                let lookup = |load: TokenStream, missing: TokenStream| match &name {
+                    Some(name) if default_value.is_some() => quote_spanned! {ident.span()=>
+                        if save.try_start_field_aliased(#name, &[#(#aliases),*])? {
+                            #load
+                        } else {
+                            #missing
+                        }
+                    },
+                    Some(name) if aliases.is_empty() => quote_spanned! {ident.span()=>
+                        save.start_field(#name)?;
+                        #load
+                    },
+                    Some(name) => quote_spanned! {ident.span()=>
+                        save.start_field_aliased(#name, &[#(#aliases),*])?;
+                        #load
+                    },
+                    None => load,
+                };
+
                 if !only_load {
-                    let name = name.as_ref().into_iter();
                     load_in_place.push(match load_in_place_kind.unwrap() {
                         LoadStoreKind::Default => {
-                            quote_spanned! {ident.span()=> {
-                                #(save.start_field(#name)?;)*
-                                save.load_into(#ident)?;
-                            }}
+                            let load = lookup(
+                                quote_spanned! {ident.span()=> save.load_into(#ident)?; },
+                                quote_spanned! {ident.span()=> *#ident = #default_expr; },
+                            );
+                            quote_spanned! {ident.span()=> { #load }}
                         }
 
                         LoadStoreKind::Value(value) => {
@@ -303,32 +448,31 @@ impl FieldsData {
                         }
 
                         LoadStoreKind::Fn(value) => {
-                            quote_spanned! {ident.span()=> {
-                                #(save.start_field(#name)?;)*
-                                #value;
-                            }}
+                            let load = lookup(
+                                quote_spanned! {ident.span()=> #value; },
+                                quote_spanned! {ident.span()=> *#ident = #default_expr; },
+                            );
+                            quote_spanned! {ident.span()=> { #load }}
                         }
                     });
                 }
 
                 if !only_load_in_place {
-                    let name = name.as_ref().into_iter();
                     load.push(match load_kind {
                         LoadStoreKind::Default => {
-                            quote_spanned! {ident.span()=> {
-                                #(save.start_field(#name)?;)*
-                                save.load()?
-                            }}
+                            let load =
+                                lookup(quote_spanned! {ident.span()=> save.load()? }, default_expr);
+                            quote_spanned! {ident.span()=> { #load }}
                         }
 
                         LoadStoreKind::Value(value) => {
                             quote_spanned!(ident.span()=> {#value})
                         }
 
-                        LoadStoreKind::Fn(value) => quote_spanned! {ident.span()=> {
-                            #(save.start_field(#name)?;)*
-                            #value
-                        }},
+                        LoadStoreKind::Fn(value) => {
+                            let load = lookup(value, default_expr);
+                            quote_spanned! {ident.span()=> { #load }}
+                        }
                     });
                 }
             }
@@ -379,23 +523,29 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
         post_store,
         post_load,
         only_load_in_place,
+        by_name,
+        version,
+        migrations,
     } = LoadStoreOptions::parse(&input.attrs).unwrap_or_else(|message| panic!("{}", message));
 
     match &input.data {
         Data::Struct(data) => {
+            if by_name {
+                panic!("only enums can be stored by variant name");
+            }
+            if version.is_some() && !matches!(data.fields, Fields::Named(_)) {
+                panic!("only structs with named fields can be versioned");
+            }
+
             let FieldsData {
-                store,
-                load_in_place,
-                load,
-            } = FieldsData::parse(&data.fields, false, only_load_in_place)
-                .unwrap_or_else(|message| panic!("{}", message));
-
-            let store_fields = store.into_iter().map(proc_macro2::TokenStream::from);
-            let load_fields_in_place = load_in_place
-                .unwrap()
-                .into_iter()
-                .map(proc_macro2::TokenStream::from);
-            let load_fields = load.map(|load| load.into_iter().map(proc_macro2::TokenStream::from));
+                store: store_fields,
+                load_in_place: load_fields_in_place,
+                load: load_fields,
+            } = match FieldsData::parse(&data.fields, false, only_load_in_place) {
+                Ok(fields_data) => fields_data,
+                Err(err) => return err.to_compile_error().into(),
+            };
+            let load_fields_in_place = load_fields_in_place.unwrap();
 
             let post_load_ident = post_load
                 .as_ref()
@@ -409,12 +559,56 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                             &mut self,
                             save: &mut S__,
                         ) -> Result<(), S__::Error> {
-                            #post_load
+                            #post_load;
+                            Ok(())
                         }
                     }
                 )*
             };
 
+            // Persistent savestates of older versions are converted step by step after loading all
+            // fields, running every migration from the saved version onwards.
+            let (store_version, load_migrate, load_value_migrate, migrate_impl) = match version {
+                Some(version) => {
+                    let migration_versions = migrations.iter().map(|(from, _)| from);
+                    let migration_code = migrations.iter().map(|(_, code)| code);
+                    (
+                        quote! {
+                            save.set_struct_version(#version)?;
+                        },
+                        quote! {
+                            self.__internal_migrate(save)?;
+                        },
+                        quote! {
+                            value.__internal_migrate(save)?;
+                        },
+                        quote! {
+                            impl #impl_generics #type_name #ty_generics #where_clause {
+                                fn __internal_migrate<S__: ::emu_utils::ReadSavestate>(
+                                    &mut self,
+                                    save: &mut S__,
+                                ) -> Result<(), S__::Error> {
+                                    if S__::TRANSIENT {
+                                        return Ok(());
+                                    }
+                                    let version = save.struct_version()?;
+                                    if version > #version {
+                                        return Err(save.unsupported_struct_version(version));
+                                    }
+                                    #(
+                                        if version <= #migration_versions {
+                                            #migration_code;
+                                        }
+                                    )*
+                                    Ok(())
+                                }
+                            }
+                        },
+                    )
+                }
+                None => (quote!(), quote!(), quote!(), quote!()),
+            };
+
             let (store_fields, load_fields_in_place, load_fields) = match &data.fields {
                 Fields::Named(fields) => {
                     let struct_fields_0 = fields
@@ -428,6 +622,7 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                         quote! {
                             let #type_name { #(#struct_fields_0),* } = self;
                             save.start_struct()?;
+                            #store_version
                             #pre_store;
                             let #type_name { #(#struct_fields_1),* } = self;
                             #(#store_fields;)*
@@ -438,6 +633,7 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                             let #type_name { #(#struct_fields_2),* } = self;
                             save.start_struct()?;
                             #(#load_fields_in_place;)*
+                            #load_migrate
                             #post_load;
                             save.end_struct()?;
                         },
@@ -447,7 +643,8 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                                 let mut value = #type_name {
                                     #(#struct_fields_3: #load_fields),*
                                 };
-                                #(value.#post_load_ident_();)*
+                                #load_value_migrate
+                                #(value.#post_load_ident_(save)?;)*
                                 save.end_struct()?;
                                 Ok(value)
                             }
@@ -476,7 +673,7 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                         load_fields.map(|load_fields| {
                             quote! {
                                 let mut value = #type_name(#(#load_fields),*);
-                                #(value.#post_load_ident_();)*
+                                #(value.#post_load_ident_(save)?;)*
                                 Ok(value)
                             }
                         }),
@@ -493,7 +690,7 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                     },
                     Some(quote! {
                         let mut value = #type_name;
-                        #(value.#post_load_ident_();)*
+                        #(value.#post_load_ident_(save)?;)*
                         Ok(value)
                     }),
                 ),
@@ -549,6 +746,7 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 
             quote! {
                 #storable_impl
+                #migrate_impl
                 #loadable_in_place_impl
                 #loadable_impl
             }
@@ -556,37 +754,185 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
         }
 
         Data::Enum(data) => {
-            let discr_bits = (32
-                - (u32::try_from(data.variants.len()).expect("too many variants")).leading_zeros())
-            .next_power_of_two()
-            .max(8);
-            let discr_ty = format_ident!("u{}", discr_bits);
+            if version.is_some() {
+                panic!("only structs with named fields can be versioned");
+            }
 
-            let variants_data = data
-                .variants
+            // Variants are identified by their explicit tag if present, or by their discriminant
+            // otherwise, so that reordering or inserting variants doesn't affect existing saves.
+            let mut next_discr = Some(0_i128);
+            let mut tags = Vec::with_capacity(data.variants.len());
+            for variant in &data.variants {
+                let discr = match &variant.discriminant {
+                    Some((_, expr)) => parse_int_expr(expr),
+                    None => next_discr,
+                };
+                next_discr = discr.and_then(|discr| discr.checked_add(1));
+                let tag = match parse_variant_tag(&variant.attrs) {
+                    Ok(tag) => tag.or(discr),
+                    Err(err) => return err.to_compile_error().into(),
+                };
+                let Some(tag) = tag else {
+                    return syn::Error::new(
+                        variant.span(),
+                        "variants with a non-literal discriminant need an explicit \
+                         #[savestate(tag = ...)]",
+                    )
+                    .to_compile_error()
+                    .into();
+                };
+                if let Some(other) = tags.iter().position(|other| *other == tag) {
+                    return syn::Error::new(
+                        variant.span(),
+                        format!(
+                            "savestate tag {} is already used by variant `{}`",
+                            tag, data.variants[other].ident
+                        ),
+                    )
+                    .to_compile_error()
+                    .into();
+                }
+                tags.push(tag);
+            }
+
+            let discr_signed = tags.iter().any(|&tag| tag < 0);
+            let discr_bits = tags
                 .iter()
-                .enumerate()
-                .map(|(discr, variant)| {
-                    let discr_literal = Lit::Int(LitInt::new(
-                        &format!("{}_u{}", discr, discr_bits),
+                .map(|&tag| {
+                    if discr_signed {
+                        129 - (if tag < 0 { !tag } else { tag }).leading_zeros()
+                    } else {
+                        128 - tag.leading_zeros()
+                    }
+                })
+                .max()
+                .unwrap_or(0)
+                .next_power_of_two()
+                .max(8);
+            let discr_ty = format_ident!("{}{}", if discr_signed { 'i' } else { 'u' }, discr_bits);
+
+            // In persistent savestates, enums stored by name are wrapped in a struct with a single
+            // field named after the active variant.
+            let store_end_variant = if by_name {
+                quote! {
+                    if !S__::TRANSIENT {
+                        save.end_struct()?;
+                    }
+                }
+            } else {
+                quote!()
+            };
+
+            let discr_literals = tags
+                .iter()
+                .map(|&tag| {
+                    let literal = Lit::Int(LitInt::new(
+                        &format!("{}_{}", tag.unsigned_abs(), discr_ty),
                         Span::call_site().into(),
                     ));
+                    if tag < 0 {
+                        quote!(-#literal)
+                    } else {
+                        quote!(#literal)
+                    }
+                })
+                .collect::<Vec<_>>();
+            let variant_name_literals = data
+                .variants
+                .iter()
+                .map(|variant| {
+                    LitByteStr::new(variant.ident.to_string().as_bytes(), variant.ident.span())
+                })
+                .collect::<Vec<_>>();
+
+            let (load_discr, load_end_variant) = if by_name {
+                (
+                    quote! {
+                        if S__::TRANSIENT {
+                            save.load_raw::<#discr_ty>()?
+                        } else {
+                            save.start_struct()?;
+                            #(
+                                if save.try_start_field_aliased(#variant_name_literals, &[])? {
+                                    #discr_literals
+                                } else
+                            )* {
+                                return Err(save.invalid_enum());
+                            }
+                        }
+                    },
+                    quote! {
+                        if !S__::TRANSIENT && !save.legacy_enum_indices() {
+                            save.end_struct()?;
+                        }
+                    },
+                )
+            } else {
+                (quote!(save.load_raw::<#discr_ty>()?), quote!())
+            };
+
+            // Version 1 of the persistent format stored the variant's index instead, using the
+            // smallest integer type that fits the number of variants.
+            let legacy_discr_bits = (32 - (data.variants.len() as u32).leading_zeros())
+                .next_power_of_two()
+                .max(8);
+            let legacy_discr_ty = format_ident!("u{}", legacy_discr_bits);
+            let legacy_discr_literals = (0..data.variants.len()).map(|i| {
+                LitInt::new(
+                    &format!("{}_{}", i, legacy_discr_ty),
+                    Span::call_site().into(),
+                )
+            });
+            let load_discr = quote! {
+                if save.legacy_enum_indices() {
+                    match save.load_raw::<#legacy_discr_ty>()? {
+                        #(#legacy_discr_literals => #discr_literals,)*
+                        _ => return Err(save.invalid_enum()),
+                    }
+                } else {
+                    #load_discr
+                }
+            };
+
+            let variants_fields_data = match data
+                .variants
+                .iter()
+                .map(|variant| {
+                    FieldsData::parse(&variant.fields, !only_load_in_place, only_load_in_place)
+                })
+                .collect::<syn::parse::Result<Vec<_>>>()
+            {
+                Ok(variants_fields_data) => variants_fields_data,
+                Err(err) => return err.to_compile_error().into(),
+            };
+
+            let variants_data = data
+                .variants
+                .iter()
+                .zip(variants_fields_data)
+                .zip(discr_literals.iter().zip(&variant_name_literals))
+                .map(|((variant, fields), (discr_literal, name_literal))| {
+                    let store_start_variant = quote! {
+                        save.store_raw(#discr_literal);
+                    };
+                    let store_start_variant = if by_name {
+                        quote! {
+                            if S__::TRANSIENT {
+                                #store_start_variant
+                            } else {
+                                save.start_struct()?;
+                                save.start_field(#name_literal)?;
+                            }
+                        }
+                    } else {
+                        store_start_variant
+                    };
 
                     let FieldsData {
-                        store,
-                        load_in_place,
-                        load,
-                    } = FieldsData::parse(&variant.fields, !only_load_in_place, only_load_in_place)
-                        .unwrap_or_else(|message| panic!("{}", message));
-
-                    let store_fields = store.into_iter().map(proc_macro2::TokenStream::from);
-                    let load_fields_in_place = load_in_place.map(|load_in_place| {
-                        load_in_place
-                            .into_iter()
-                            .map(proc_macro2::TokenStream::from)
-                    });
-                    let load_fields =
-                        load.map(|load| load.into_iter().map(proc_macro2::TokenStream::from));
+                        store: store_fields,
+                        load_in_place: load_fields_in_place,
+                        load: load_fields,
+                    } = fields;
 
                     let variant_name = &variant.ident;
 
@@ -602,10 +948,11 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                                     #type_name::#variant_name {
                                         #(#variant_fields_0),*
                                     } => {
-                                        save.store_raw(#discr_literal);
+                                        #store_start_variant
                                         save.start_struct()?;
                                         #(#store_fields;)*
                                         save.end_struct()?;
+                                        #store_end_variant
                                     }
                                 },
                                 if only_load_in_place {
@@ -619,7 +966,7 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                                                 #(#load_fields_in_place;)*
                                                 save.end_struct()?;
                                             } else {
-                                                return Err(S__::invalid_enum());
+                                                return Err(save.invalid_enum());
                                             }
                                         }
                                     }
@@ -646,8 +993,9 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                             (
                                 quote! {
                                     #type_name::#variant_name(#(#variant_fields_0),*) => {
-                                        save.store_raw(#discr_literal);
+                                        #store_start_variant
                                         #(#store_fields;)*
+                                        #store_end_variant
                                     }
                                 },
                                 if only_load_in_place {
@@ -659,7 +1007,7 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                                             ) = self {
                                                 #(#load_fields_in_place;)*
                                             } else {
-                                                return Err(S__::invalid_enum());
+                                                return Err(save.invalid_enum());
                                             }
                                         }
                                     }
@@ -677,14 +1025,15 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                         Fields::Unit => (
                             quote! {
                                 #type_name::#variant_name => {
-                                    save.store_raw(#discr_literal);
+                                    #store_start_variant
+                                    #store_end_variant
                                 }
                             },
                             if only_load_in_place {
                                 quote! {
                                     #discr_literal => {
                                         if !matches!(self, #type_name::#variant_name) {
-                                            return Err(S__::invalid_enum());
+                                            return Err(save.invalid_enum());
                                         }
                                     }
                                 }
@@ -733,11 +1082,12 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                             &mut self,
                             save: &mut S__,
                         ) -> Result<(), S__::Error> {
-                            let discriminant = save.load_raw::<#discr_ty>()?;
+                            let discriminant = #load_discr;
                             match discriminant {
                                 #(#load_variants)*
-                                _ => return Err(S__::invalid_enum()),
+                                _ => return Err(save.invalid_enum()),
                             };
+                            #load_end_variant
                             #post_load;
                             Ok(())
                         }
@@ -756,7 +1106,7 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                                 &mut self,
                                 save: &mut S__,
                             ) -> Result<(), S__::Error> {
-                                #post_load
+                                #post_load;
                                 Ok(())
                             }
                         }
@@ -769,12 +1119,13 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                         fn load<S__: ::emu_utils::ReadSavestate>(
                             save: &mut S__,
                         ) -> Result<Self, S__::Error> {
-                            let discriminant = save.load_raw::<#discr_ty>()?;
+                            let discriminant = #load_discr;
                             let mut value = match discriminant {
                                 #(#load_variants)*
-                                _ => return Err(S__::invalid_enum()),
+                                _ => return Err(save.invalid_enum()),
                             };
-                            #(value.#post_load_ident_();)*
+                            #load_end_variant
+                            #(value.#post_load_ident_(save)?;)*
                             Ok(value)
                         }
                     }
@@ -0,0 +1,49 @@
+use emu_utils::{diff_saves, FieldChange, SaveInspector};
+use std::{env, ffi::OsString, fs, process::exit};
+
+fn read_save(path: &OsString) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|err| {
+        eprintln!("Couldn't read {}: {err}", path.to_string_lossy());
+        exit(2);
+    })
+}
+
+fn inspect<'a>(path: &OsString, save: &'a [u8]) -> SaveInspector<'a> {
+    SaveInspector::new(save).unwrap_or_else(|err| {
+        eprintln!("Couldn't parse {}: {err}", path.to_string_lossy());
+        exit(2);
+    })
+}
+
+// Exits with 0 if both savestates have the same contents, 1 if they differ and 2 on errors.
+fn main() {
+    let args = env::args_os().skip(1).collect::<Vec<_>>();
+    let [old_path, new_path] = &args[..] else {
+        eprintln!("Usage: savestate-diff <old savestate> <new savestate>");
+        exit(2);
+    };
+
+    let (old_save, new_save) = (read_save(old_path), read_save(new_path));
+    let old = inspect(old_path, &old_save);
+    let new = inspect(new_path, &new_save);
+
+    let diffs = diff_saves(&old, &new);
+    for diff in &diffs {
+        let path = if diff.path.is_empty() {
+            "<root>"
+        } else {
+            &diff.path
+        };
+        match diff.change {
+            FieldChange::Added => println!("+ {path}: {}", diff.new.unwrap()),
+            FieldChange::Removed => println!("- {path}: {}", diff.old.unwrap()),
+            FieldChange::Changed => {
+                println!("~ {path}: {} -> {}", diff.old.unwrap(), diff.new.unwrap());
+            }
+        }
+    }
+
+    if !diffs.is_empty() {
+        exit(1);
+    }
+}
@@ -0,0 +1,52 @@
+use emu_utils::{SaveInspector, SaveNode};
+use std::{env, fs, process::exit};
+
+fn print_node(inspector: &SaveInspector, name: &str, node: &SaveNode, depth: usize) {
+    let indent = depth * 2;
+    match &node.fields {
+        Some(fields) => {
+            println!(
+                "{:indent$}{name} @ {:#x}, {} bytes {{",
+                "", node.offset, node.len
+            );
+            for field in fields {
+                print_node(inspector, &field.name, &field.value, depth + 1);
+            }
+            println!("{:indent$}}}", "");
+        }
+        None => println!(
+            "{:indent$}{name} @ {:#x}: {}",
+            "",
+            node.offset,
+            inspector.value(node)
+        ),
+    }
+}
+
+fn main() {
+    let Some(path) = env::args_os().nth(1) else {
+        eprintln!("Usage: savestate-dump <savestate>");
+        exit(2);
+    };
+
+    let save = match fs::read(&path) {
+        Ok(save) => save,
+        Err(err) => {
+            eprintln!("Couldn't read {}: {err}", path.to_string_lossy());
+            exit(1);
+        }
+    };
+    let inspector = match SaveInspector::new(&save) {
+        Ok(inspector) => inspector,
+        Err(err) => {
+            eprintln!("Couldn't parse {}: {err}", path.to_string_lossy());
+            exit(1);
+        }
+    };
+
+    let info = inspector.info();
+    println!("Emulator: {} (version {})", info.emu_id, info.emu_version);
+    println!("Timestamp: {}", info.timestamp);
+    println!("Format version: {}", inspector.format_version());
+    print_node(&inspector, "<root>", &inspector.root(), 0);
+}
@@ -342,7 +342,7 @@ macro_rules! bounded_int_savestate {
             #[inline]
             fn load<S: $crate::ReadSavestate>(save: &mut S) -> Result<Self, S::Error> {
                 save.load::<$inner>()
-                    .and_then(|v| Self::new_checked(v).ok_or_else(|| S::invalid_enum()))
+                    .and_then(|v| Self::new_checked(v).ok_or_else(|| save.invalid_enum()))
             }
         }
 
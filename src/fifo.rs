@@ -18,7 +18,10 @@ where
         save.start_struct()?;
 
         save.start_field(b"len")?;
-        let len = save.load_raw::<u32>()? as usize;
+        let len = save.load_array_len()?;
+        if !S::TRANSIENT && len > CAPACITY {
+            return Err(save.invalid_length(CAPACITY as u64, len as u64));
+        }
 
         save.start_field(b"buffer")?;
         let mut buffer = [MaybeUninit::uninit(); CAPACITY];
@@ -51,13 +54,16 @@ where
         save.start_struct()?;
 
         save.start_field(b"len")?;
-        self.len = save.load_raw::<u32>()? as usize;
+        let len = save.load_array_len()?;
+        if !S::TRANSIENT && len > CAPACITY {
+            return Err(save.invalid_length(CAPACITY as u64, len as u64));
+        }
 
         save.start_field(b"buffer")?;
         let slice = if S::TRANSIENT {
-            unsafe { self.buffer.get_unchecked_mut(..self.len) }
+            unsafe { self.buffer.get_unchecked_mut(..len) }
         } else {
-            &mut self.buffer[..self.len]
+            &mut self.buffer[..len]
         };
         for elem in slice {
             *elem = MaybeUninit::new(save.load()?);
@@ -65,8 +71,10 @@ where
 
         save.end_struct()?;
 
+        // Only update the length once all elements are initialized
+        self.len = len;
         self.read_pos = 0;
-        self.write_pos = if self.len == CAPACITY { 0 } else { self.len };
+        self.write_pos = if len == CAPACITY { 0 } else { len };
 
         Ok(())
     }
@@ -222,3 +230,64 @@ impl<T: Copy, const CAPACITY: usize> Default for Fifo<T, CAPACITY> {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{write_persistent, PersistentReadSavestate, WriteOptions};
+
+    fn persistent_fifo(len: usize, values: &[u8]) -> Vec<u8> {
+        write_persistent(WriteOptions::default(), |writer| {
+            writer.start_struct()?;
+            writer.start_field(b"len")?;
+            writer.store_array_len(len)?;
+            writer.start_field(b"buffer")?;
+            for &value in values {
+                writer.store_raw(value);
+            }
+            writer.end_struct()
+        })
+    }
+
+    #[test]
+    fn load_over_capacity() {
+        let save = persistent_fifo(5, &[1, 2, 3, 4, 5]);
+        assert!(PersistentReadSavestate::new(&save)
+            .unwrap()
+            .load::<Fifo<u8, 4>>()
+            .is_err());
+
+        let mut fifo = Fifo::<u8, 4>::new();
+        fifo.write(9).unwrap();
+        assert!(PersistentReadSavestate::new(&save)
+            .unwrap()
+            .load_into(&mut fifo)
+            .is_err());
+        assert_eq!(fifo.len(), 1);
+        assert_eq!(fifo.read(), Some(9));
+    }
+
+    #[test]
+    fn failed_load_in_place_keeps_len() {
+        // The length claims more elements than are present
+        let save = persistent_fifo(3, &[1]);
+        let mut fifo = Fifo::<u8, 4>::new();
+        fifo.write(9).unwrap();
+        assert!(PersistentReadSavestate::new(&save)
+            .unwrap()
+            .load_into(&mut fifo)
+            .is_err());
+        assert_eq!(fifo.len(), 1);
+
+        let save = persistent_fifo(3, &[1, 2, 3]);
+        PersistentReadSavestate::new(&save)
+            .unwrap()
+            .load_into(&mut fifo)
+            .unwrap();
+        assert_eq!(
+            (fifo.read(), fifo.read(), fifo.read()),
+            (Some(1), Some(2), Some(3))
+        );
+        assert!(fifo.is_empty());
+    }
+}
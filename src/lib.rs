@@ -33,6 +33,8 @@ pub mod triple_buffer;
 
 pub mod mem_prelude {
     pub use crate::MemValue;
-    pub use crate::{BoxedByteSlice, Bytes, OwnedByteSliceCellPtr, OwnedBytesCellPtr};
+    pub use crate::{
+        BoxedByteSlice, Bytes, DirtyTracked, OwnedByteSliceCellPtr, OwnedBytesCellPtr,
+    };
     pub use crate::{ByteMutSlice, ByteMutSliceOwnedPtr, ByteSlice};
 }
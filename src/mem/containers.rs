@@ -1,5 +1,6 @@
 use super::{Fill8, MemValue, Zero};
 use core::{
+    cell::Cell,
     mem,
     ops::{Deref, DerefMut},
     ptr,
@@ -725,3 +726,266 @@ impl ByteSlice for OwnedByteSliceCellPtr {
 impl ByteMutSliceOwnedPtr for OwnedByteSliceCellPtr {
     impl_writes!();
 }
+
+// A bitmap of the pages of a byte buffer that were written to since it was last cleared.
+pub struct DirtyPages {
+    words: Box<[Cell<u64>]>,
+    pages: usize,
+}
+
+impl DirtyPages {
+    pub const PAGE_SHIFT: u32 = 12;
+    pub const PAGE_SIZE: usize = 1 << Self::PAGE_SHIFT;
+
+    // Creates a bitmap for a buffer of `len` bytes, with all pages marked as dirty.
+    pub fn new(len: usize) -> Self {
+        let pages = len.div_ceil(Self::PAGE_SIZE);
+        let result = DirtyPages {
+            words: (0..pages.div_ceil(64)).map(|_| Cell::new(0)).collect(),
+            pages,
+        };
+        result.mark_all();
+        result
+    }
+
+    #[inline]
+    pub fn page_count(&self) -> usize {
+        self.pages
+    }
+
+    #[inline]
+    pub fn is_dirty(&self, page: usize) -> bool {
+        self.words[page >> 6].get() & 1 << (page & 63) != 0
+    }
+
+    #[inline]
+    pub fn mark(&self, off: usize, len: usize) {
+        if len == 0 {
+            return;
+        }
+        let start = off >> Self::PAGE_SHIFT;
+        let end = (off + len - 1) >> Self::PAGE_SHIFT;
+        for page in start..=end {
+            let word = &self.words[page >> 6];
+            word.set(word.get() | 1 << (page & 63));
+        }
+    }
+
+    pub fn mark_all(&self) {
+        for (i, word) in self.words.iter().enumerate() {
+            let remaining = self.pages - (i << 6);
+            word.set(if remaining >= 64 {
+                !0
+            } else {
+                (1 << remaining) - 1
+            });
+        }
+    }
+
+    pub fn clear(&self) {
+        for word in self.words.iter() {
+            word.set(0);
+        }
+    }
+
+    pub fn iter_dirty(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, word)| {
+            let mut bits = word.get();
+            core::iter::from_fn(move || {
+                if bits == 0 {
+                    return None;
+                }
+                let bit = bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                Some(i << 6 | bit)
+            })
+        })
+    }
+}
+
+// Wraps a byte buffer to record the pages modified through its `ByteMutSlice` or
+// `ByteMutSliceOwnedPtr` methods, so that transient savestates can skip unchanged pages. Writes
+// made through raw pointers or `inner` aren't tracked, and need to be reported with `mark_dirty`;
+// `inner_mut` conservatively marks the whole buffer as dirty.
+pub struct DirtyTracked<T> {
+    inner: T,
+    dirty: DirtyPages,
+}
+
+mod sealed {
+    pub trait Trackable {
+        fn byte_len(&self) -> usize;
+    }
+}
+
+// Byte buffers that can be wrapped in a `DirtyTracked`.
+pub trait Trackable: sealed::Trackable {}
+
+impl<T: Trackable> DirtyTracked<T> {
+    #[inline]
+    pub fn new(inner: T) -> Self {
+        DirtyTracked {
+            dirty: DirtyPages::new(inner.byte_len()),
+            inner,
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.inner.byte_len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> DirtyTracked<T> {
+    #[inline]
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut T {
+        self.dirty.mark_all();
+        &mut self.inner
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    #[inline]
+    pub fn dirty_pages(&self) -> &DirtyPages {
+        &self.dirty
+    }
+
+    #[inline]
+    pub fn mark_dirty(&self, off: usize, len: usize) {
+        self.dirty.mark(off, len);
+    }
+}
+
+macro_rules! impl_tracked_writes {
+    ($($mut: ident)?) => {
+        #[inline]
+        unsafe fn write_unchecked(&$($mut)* self, off: usize, value: u8) {
+            self.dirty.mark(off, 1);
+            self.inner.write_unchecked(off, value)
+        }
+
+        #[inline]
+        fn write(&$($mut)* self, off: usize, value: u8) {
+            self.inner.write(off, value);
+            self.dirty.mark(off, 1);
+        }
+
+        #[inline]
+        unsafe fn write_le_unchecked<T: MemValue>(&$($mut)* self, off: usize, value: T) {
+            self.dirty.mark(off, mem::size_of::<T>());
+            self.inner.write_le_unchecked(off, value)
+        }
+
+        #[inline]
+        fn write_le<T: MemValue>(&$($mut)* self, off: usize, value: T) {
+            self.inner.write_le(off, value);
+            self.dirty.mark(off, mem::size_of::<T>());
+        }
+
+        #[inline]
+        unsafe fn write_le_aligned_unchecked<T: MemValue>(&$($mut)* self, off: usize, value: T) {
+            self.dirty.mark(off, mem::size_of::<T>());
+            self.inner.write_le_aligned_unchecked(off, value)
+        }
+
+        #[inline]
+        unsafe fn write_le_aligned<T: MemValue>(&$($mut)* self, off: usize, value: T) {
+            self.inner.write_le_aligned(off, value);
+            self.dirty.mark(off, mem::size_of::<T>());
+        }
+
+        #[inline]
+        unsafe fn write_be_unchecked<T: MemValue>(&$($mut)* self, off: usize, value: T) {
+            self.dirty.mark(off, mem::size_of::<T>());
+            self.inner.write_be_unchecked(off, value)
+        }
+
+        #[inline]
+        fn write_be<T: MemValue>(&$($mut)* self, off: usize, value: T) {
+            self.inner.write_be(off, value);
+            self.dirty.mark(off, mem::size_of::<T>());
+        }
+
+        #[inline]
+        unsafe fn write_be_aligned_unchecked<T: MemValue>(&$($mut)* self, off: usize, value: T) {
+            self.dirty.mark(off, mem::size_of::<T>());
+            self.inner.write_be_aligned_unchecked(off, value)
+        }
+
+        #[inline]
+        unsafe fn write_be_aligned<T: MemValue>(&$($mut)* self, off: usize, value: T) {
+            self.inner.write_be_aligned(off, value);
+            self.dirty.mark(off, mem::size_of::<T>());
+        }
+
+        #[inline]
+        unsafe fn write_ne_unchecked<T: MemValue>(&$($mut)* self, off: usize, value: T) {
+            self.dirty.mark(off, mem::size_of::<T>());
+            self.inner.write_ne_unchecked(off, value)
+        }
+
+        #[inline]
+        fn write_ne<T: MemValue>(&$($mut)* self, off: usize, value: T) {
+            self.inner.write_ne(off, value);
+            self.dirty.mark(off, mem::size_of::<T>());
+        }
+
+        #[inline]
+        unsafe fn write_ne_aligned_unchecked<T: MemValue>(&$($mut)* self, off: usize, value: T) {
+            self.dirty.mark(off, mem::size_of::<T>());
+            self.inner.write_ne_aligned_unchecked(off, value)
+        }
+
+        #[inline]
+        unsafe fn write_ne_aligned<T: MemValue>(&$($mut)* self, off: usize, value: T) {
+            self.inner.write_ne_aligned(off, value);
+            self.dirty.mark(off, mem::size_of::<T>());
+        }
+    };
+}
+
+macro_rules! impl_dirty_tracked {
+    ($ty: ty, $write_trait: ident$(, $mut: ident)?$(; const $len: ident)?) => {
+        impl$(<const $len: usize>)? sealed::Trackable for $ty {
+            #[inline]
+            fn byte_len(&self) -> usize {
+                self.len()
+            }
+        }
+
+        impl$(<const $len: usize>)? Trackable for $ty {}
+
+        impl$(<const $len: usize>)? DirtyTracked<$ty> {
+            #[inline]
+            pub fn as_ptr(&self) -> *const u8 {
+                self.inner.as_ptr()
+            }
+        }
+
+        impl$(<const $len: usize>)? ByteSlice for DirtyTracked<$ty> {
+            impl_reads!();
+        }
+
+        impl$(<const $len: usize>)? $write_trait for DirtyTracked<$ty> {
+            impl_tracked_writes!($($mut)?);
+        }
+    };
+}
+
+impl_dirty_tracked!(Bytes<LEN>, ByteMutSlice, mut; const LEN);
+impl_dirty_tracked!(BoxedByteSlice, ByteMutSlice, mut);
+impl_dirty_tracked!(OwnedBytesCellPtr<LEN>, ByteMutSliceOwnedPtr; const LEN);
+impl_dirty_tracked!(OwnedByteSliceCellPtr, ByteMutSliceOwnedPtr);
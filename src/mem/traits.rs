@@ -50,17 +50,25 @@ pub fn make_zero<T: Zero + ?Sized>(v: &mut T) {
 }
 
 mod sealed {
-    pub trait MemValue {}
+    pub trait MemValue {
+        const SIGNED: bool;
+        const FLOAT: bool;
+    }
 
     macro_rules! impl_mem_value {
-        ($($ty: ty),*) => {
+        ($signed: literal, $float: literal; $($ty: ty),*) => {
             $(
-                impl MemValue for $ty {}
+                impl MemValue for $ty {
+                    const SIGNED: bool = $signed;
+                    const FLOAT: bool = $float;
+                }
             )*
         };
     }
 
-    impl_mem_value!(u8, i8, u16, i16, u32, i32, u64, i64, u128, i128, usize, isize, f32, f64);
+    impl_mem_value!(false, false; u8, u16, u32, u64, u128, usize);
+    impl_mem_value!(true, false; i8, i16, i32, i64, i128, isize);
+    impl_mem_value!(true, true; f32, f64);
 }
 
 pub trait MemValue: Sized + Copy + Zero + Fill8 + sealed::MemValue {
@@ -1,4 +1,52 @@
+mod checksum;
+mod compress;
+pub use compress::{MAX_COMPRESSION_LEVEL, MIN_COMPRESSION_LEVEL};
+mod delta;
+pub use delta::*;
+mod diff;
+pub use diff::*;
+#[cfg(any(test, feature = "testing"))]
+mod harness;
+#[cfg(any(test, feature = "testing"))]
+pub use harness::*;
+mod header;
+pub use header::SaveInfo;
+mod inspect;
+pub use inspect::*;
 mod read;
 pub use read::*;
+mod rewind;
+pub use rewind::*;
+mod stream;
+pub use stream::*;
+mod text;
+pub use text::*;
+#[cfg(debug_assertions)]
+mod validate;
+mod value_type;
+pub use value_type::*;
+mod varint;
 mod write;
 pub use write::*;
+
+/// Enum variants sharing a savestate tag are rejected when deriving `Savestate`:
+///
+/// ```compile_fail
+/// #[derive(emu_utils::Savestate)]
+/// enum Mode {
+///     A = 1,
+///     #[savestate(tag = 1)]
+///     B = 2,
+/// }
+/// ```
+///
+/// ```
+/// #[derive(emu_utils::Savestate)]
+/// enum Mode {
+///     A = 1,
+///     #[savestate(tag = 3)]
+///     B = 2,
+/// }
+/// ```
+#[cfg(doctest)]
+struct TagCollision;
@@ -0,0 +1,181 @@
+// CRC-32 (ISO-HDLC, as used by zlib and PNG), used to detect corrupted persistent savestates.
+
+const TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut value = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            value = if value & 1 != 0 {
+                (value >> 1) ^ 0xEDB8_8320
+            } else {
+                value >> 1
+            };
+            bit += 1;
+        }
+        table[i] = value;
+        i += 1;
+    }
+    table
+};
+
+#[derive(Clone, Copy)]
+pub(super) struct Crc32(u32);
+
+impl Crc32 {
+    #[inline]
+    pub fn new() -> Self {
+        Crc32(!0)
+    }
+
+    #[inline]
+    pub fn update(&mut self, data: &[u8]) {
+        let mut crc = self.0;
+        for &byte in data {
+            crc = TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+        }
+        self.0 = crc;
+    }
+
+    #[inline]
+    pub fn finish(self) -> u32 {
+        !self.0
+    }
+}
+
+// Multiplies a 32x32 matrix over GF(2), stored as columns, by a vector.
+fn gf2_matrix_times(matrix: &[u32; 32], mut vector: u32) -> u32 {
+    let mut result = 0;
+    let mut i = 0;
+    while vector != 0 {
+        if vector & 1 != 0 {
+            result ^= matrix[i];
+        }
+        vector >>= 1;
+        i += 1;
+    }
+    result
+}
+
+fn gf2_matrix_square(matrix: &[u32; 32]) -> [u32; 32] {
+    let mut result = [0; 32];
+    for (column, value) in result.iter_mut().zip(matrix) {
+        *column = gf2_matrix_times(matrix, *value);
+    }
+    result
+}
+
+// Returns the change in the CRC-32 of a message caused by XORing `diff` into it, `zeros_after`
+// bytes before its end. As CRCs are affine, this is the same for any message, which allows
+// patching already checksummed data.
+pub(super) fn crc32_patch(diff: &[u8], mut zeros_after: u64) -> u32 {
+    let mut crc = Crc32(0);
+    crc.update(diff);
+    let mut result = crc.0;
+
+    // Apply the operator for `zeros_after` zero bytes, built by squaring the one for a single zero
+    // bit
+    let mut operator = [0; 32];
+    operator[0] = 0xEDB8_8320;
+    for (i, column) in operator.iter_mut().enumerate().skip(1) {
+        *column = 1 << (i - 1);
+    }
+    for _ in 0..3 {
+        operator = gf2_matrix_square(&operator);
+    }
+    while zeros_after != 0 {
+        if zeros_after & 1 != 0 {
+            result = gf2_matrix_times(&operator, result);
+        }
+        zeros_after >>= 1;
+        if zeros_after != 0 {
+            operator = gf2_matrix_square(&operator);
+        }
+    }
+    result
+}
+
+#[inline]
+pub(super) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        write_persistent, PersistentReadSavestate, ReadErrorKind, StreamReadSavestate,
+        WriteOptions, WriteSavestate,
+    };
+    use std::io::Cursor;
+
+    #[test]
+    fn check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn incremental() {
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn patch() {
+        let mut data = (0..1000_u32).map(|i| (i * 7) as u8).collect::<Vec<_>>();
+        let original = crc32(&data);
+        for (pos, diff) in [(0, &[1][..]), (100, &[0xFF; 8]), (992, &[3; 8]), (500, &[])] {
+            for (byte, diff) in data[pos..].iter_mut().zip(diff) {
+                *byte ^= diff;
+            }
+            let zeros_after = (data.len() - pos - diff.len()) as u64;
+            assert_eq!(crc32(&data), original ^ crc32_patch(diff, zeros_after));
+            for (byte, diff) in data[pos..].iter_mut().zip(diff) {
+                *byte ^= diff;
+            }
+        }
+    }
+
+    fn checksummed_save(compression: Option<u8>) -> Vec<u8> {
+        write_persistent(
+            WriteOptions {
+                checksum: true,
+                compression,
+            },
+            |writer| writer.store(&mut (0..100_u32).collect::<Vec<_>>()),
+        )
+    }
+
+    #[test]
+    fn mismatch() {
+        for compression in [None, Some(1)] {
+            let save = checksummed_save(compression);
+            assert!(PersistentReadSavestate::new(&save).is_ok());
+            // Flip a byte in the header, the body and the checksum itself
+            for pos in [12, save.len() / 2, save.len() - 1] {
+                let mut save = save.clone();
+                save[pos] ^= 0x10;
+                assert_eq!(
+                    PersistentReadSavestate::new(&save).err().unwrap().kind,
+                    ReadErrorKind::ChecksumMismatch
+                );
+                if compression.is_none() {
+                    assert_eq!(
+                        StreamReadSavestate::new(Cursor::new(&save))
+                            .err()
+                            .unwrap()
+                            .kind,
+                        ReadErrorKind::ChecksumMismatch
+                    );
+                }
+            }
+        }
+    }
+}
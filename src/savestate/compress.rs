@@ -0,0 +1,273 @@
+// LZ77 compression using LZ4's block encoding: each sequence is a token (literal length in the
+// high nibble, match length minus `MIN_MATCH` in the low one), optional length extension bytes,
+// the literals, and a 16-bit little-endian match offset; the last sequence only has literals.
+
+const MIN_MATCH: usize = 4;
+const MAX_OFFSET: usize = 0xFFFF;
+const HASH_BITS: u32 = 16;
+
+pub const MIN_COMPRESSION_LEVEL: u8 = 1;
+pub const MAX_COMPRESSION_LEVEL: u8 = 9;
+
+#[inline]
+fn hash(value: u32) -> usize {
+    (value.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+}
+
+#[inline]
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+fn write_len_ext(output: &mut Vec<u8>, mut len: usize) {
+    while len >= 0xFF {
+        output.push(0xFF);
+        len -= 0xFF;
+    }
+    output.push(len as u8);
+}
+
+fn write_sequence(output: &mut Vec<u8>, literals: &[u8], match_: Option<(usize, usize)>) {
+    let lit_len = literals.len();
+    let match_len_code = match_.map_or(0, |(_, len)| len - MIN_MATCH);
+    output.push(((lit_len.min(15) as u8) << 4) | match_len_code.min(15) as u8);
+    if lit_len >= 15 {
+        write_len_ext(output, lit_len - 15);
+    }
+    output.extend_from_slice(literals);
+    if let Some((offset, _)) = match_ {
+        output.extend_from_slice(&(offset as u16).to_le_bytes());
+        if match_len_code >= 15 {
+            write_len_ext(output, match_len_code - 15);
+        }
+    }
+}
+
+// Higher levels follow longer hash chains when looking for matches, trading speed for size; each
+// input position compares against at most 4096 earlier candidates, at level 9.
+pub(super) fn compress(input: &[u8], level: u8, output: &mut Vec<u8>) {
+    let level = level.clamp(MIN_COMPRESSION_LEVEL, MAX_COMPRESSION_LEVEL);
+    let max_attempts = 1_usize << ((level - 1) as u32 * 3 / 2);
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; MAX_OFFSET + 1];
+
+    let mut literal_start = 0;
+    let mut pos = 0;
+    while pos + MIN_MATCH <= input.len() {
+        let value = read_u32(input, pos);
+        let hash = hash(value);
+
+        let mut best_len = 0;
+        let mut best_offset = 0;
+        let mut candidate = head[hash];
+        let mut attempts = max_attempts;
+        while candidate != usize::MAX && pos - candidate <= MAX_OFFSET && attempts != 0 {
+            if read_u32(input, candidate) == value {
+                let len = MIN_MATCH
+                    + input[candidate + MIN_MATCH..]
+                        .iter()
+                        .zip(&input[pos + MIN_MATCH..])
+                        .take_while(|(a, b)| a == b)
+                        .count();
+                if len > best_len {
+                    best_len = len;
+                    best_offset = pos - candidate;
+                    if pos + len == input.len() {
+                        break;
+                    }
+                }
+            }
+            let next = prev[candidate & MAX_OFFSET];
+            if next >= candidate {
+                break;
+            }
+            candidate = next;
+            attempts -= 1;
+        }
+
+        let end = if best_len == 0 {
+            pos + 1
+        } else {
+            pos + best_len
+        };
+        while pos < end {
+            if pos + MIN_MATCH <= input.len() {
+                let hash = self::hash(read_u32(input, pos));
+                prev[pos & MAX_OFFSET] = head[hash];
+                head[hash] = pos;
+            }
+            pos += 1;
+        }
+
+        if best_len != 0 {
+            write_sequence(
+                output,
+                &input[literal_start..end - best_len],
+                Some((best_offset, best_len)),
+            );
+            literal_start = end;
+        }
+    }
+    write_sequence(output, &input[literal_start..], None);
+}
+
+fn read_len_ext(input: &[u8], pos: &mut usize) -> Option<usize> {
+    let mut len = 0_usize;
+    loop {
+        let byte = *input.get(*pos)?;
+        *pos += 1;
+        len = len.checked_add(byte as usize)?;
+        if byte != 0xFF {
+            return Some(len);
+        }
+    }
+}
+
+// Returns `None` if the input is malformed or doesn't decompress to exactly `len` bytes.
+pub(super) fn decompress(input: &[u8], len: usize) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(len.min(input.len().saturating_mul(255)));
+    let mut pos = 0;
+    loop {
+        let token = *input.get(pos)?;
+        pos += 1;
+
+        let mut lit_len = (token >> 4) as usize;
+        if lit_len == 15 {
+            lit_len = lit_len.checked_add(read_len_ext(input, &mut pos)?)?;
+        }
+        let literals = input.get(pos..pos.checked_add(lit_len)?)?;
+        if output.len() + lit_len > len {
+            return None;
+        }
+        output.extend_from_slice(literals);
+        pos += lit_len;
+
+        if pos == input.len() {
+            break;
+        }
+
+        let offset = u16::from_le_bytes([*input.get(pos)?, *input.get(pos + 1)?]) as usize;
+        pos += 2;
+        let mut match_len = (token & 0xF) as usize;
+        if match_len == 15 {
+            match_len = match_len.checked_add(read_len_ext(input, &mut pos)?)?;
+        }
+        match_len += MIN_MATCH;
+
+        if offset == 0 || offset > output.len() || output.len() + match_len > len {
+            return None;
+        }
+        let match_start = output.len() - offset;
+        if offset >= match_len {
+            output.extend_from_within(match_start..match_start + match_len);
+        } else {
+            for i in match_start..match_start + match_len {
+                output.push(output[i]);
+            }
+        }
+    }
+
+    if output.len() != len {
+        return None;
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pseudo_random_bytes(len: usize, mut seed: u32) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as u8
+            })
+            .collect()
+    }
+
+    fn compressed(input: &[u8], level: u8) -> Vec<u8> {
+        let mut output = Vec::new();
+        compress(input, level, &mut output);
+        assert_eq!(decompress(&output, input.len()).as_deref(), Some(input));
+        output
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(compressed(&[], 1), [0]);
+    }
+
+    #[test]
+    fn shorter_than_min_match() {
+        assert_eq!(compressed(&[1, 2, 3], 9), [0x30, 1, 2, 3]);
+    }
+
+    #[test]
+    fn long_runs() {
+        let literals = pseudo_random_bytes(15 + 255 + 300, 1);
+        compressed(&literals, 1);
+
+        let mut input = vec![7; 15 + 255 + 300];
+        input.extend_from_slice(&literals);
+        input.extend_from_slice(&literals);
+        let output = compressed(&input, 1);
+        assert!(output.len() < literals.len() + 32);
+    }
+
+    #[test]
+    fn overlapping_matches() {
+        let input = b"abc".repeat(100);
+        let output = compressed(&input, 1);
+        // A single match at offset 3 covers everything after the first literals
+        assert_eq!(&output[..6], [0x3F, b'a', b'b', b'c', 3, 0]);
+        assert!(output.len() < 10);
+    }
+
+    #[test]
+    fn max_offset() {
+        let pattern = pseudo_random_bytes(1000, 2);
+        for (gap, matched) in [(MAX_OFFSET, true), (MAX_OFFSET + 1, false)] {
+            let mut input = pattern.clone();
+            input.resize(gap, 0);
+            input.extend_from_slice(&pattern);
+            let output = compressed(&input, 9);
+            assert_eq!(output.len() < pattern.len() * 3 / 2, matched);
+        }
+    }
+
+    #[test]
+    fn levels() {
+        let mut input = Vec::new();
+        for i in 0..2000_u32 {
+            input.extend_from_slice(format!("entry {} = {:#x};\n", i % 300, i * i).as_bytes());
+        }
+        input.extend_from_slice(&pseudo_random_bytes(5000, 3));
+        let fast = compressed(&input, MIN_COMPRESSION_LEVEL);
+        let best = compressed(&input, MAX_COMPRESSION_LEVEL);
+        assert!(fast.len() < input.len() / 2);
+        assert!(best.len() <= fast.len());
+    }
+
+    #[test]
+    fn malformed() {
+        let input = b"abcd".repeat(50);
+        let output = compressed(&input, 1);
+        for len in 0..output.len() {
+            assert_eq!(decompress(&output[..len], input.len()), None);
+        }
+        assert_eq!(decompress(&output, input.len() - 1), None);
+        assert_eq!(decompress(&output, input.len() + 1), None);
+
+        // One literal followed by a 4-byte match at offset 0, 1 and 2 respectively
+        assert_eq!(decompress(&[0x10, b'a', 0, 0, 0], 5), None);
+        assert_eq!(
+            decompress(&[0x10, b'a', 1, 0, 0], 5).as_deref(),
+            Some(&b"aaaaa"[..])
+        );
+        assert_eq!(decompress(&[0x10, b'a', 2, 0, 0], 5), None);
+    }
+}
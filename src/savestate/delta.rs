@@ -0,0 +1,237 @@
+// Deltas between transient savestates, meant for rewind snapshots: since most of a snapshot is
+// identical to the previous one, only the bytes that changed are stored, XORed with the base.
+// A delta starts with the base and target lengths as varints, followed by runs, each made of the
+// number of unchanged bytes to skip and the number of changed bytes as varints, then the changed
+// bytes XORed with the base; bytes past the base's end are XORed with zero, and bytes after the
+// last run are unchanged.
+
+use super::{read::BodySource, varint::write_varint, ReadError, ReadErrorKind};
+
+// Unchanged runs shorter than this get merged into the surrounding changed bytes, as starting a new
+// run would take more space.
+const MIN_SKIP: usize = 4;
+
+// Returns the number of bytes starting at `pos` that are the same in `base` and `target`.
+fn unchanged_len(base: &[u8], target: &[u8], pos: usize) -> usize {
+    let mut end = pos;
+    let common_len = base.len().min(target.len());
+    while end + 8 <= common_len && base[end..end + 8] == target[end..end + 8] {
+        end += 8;
+    }
+    while end < target.len() && target[end] == base.get(end).copied().unwrap_or(0) {
+        end += 1;
+    }
+    end - pos
+}
+
+// Appends a delta that turns `base` into `target` to `delta`.
+pub fn encode_delta(base: &[u8], target: &[u8], delta: &mut Vec<u8>) {
+    write_varint(delta, base.len() as u64);
+    write_varint(delta, target.len() as u64);
+
+    let mut pos = 0;
+    loop {
+        let skip = unchanged_len(base, target, pos);
+        if pos + skip == target.len() {
+            break;
+        }
+        let start = pos + skip;
+        let mut end = start;
+        loop {
+            end += 1;
+            let skip = unchanged_len(base, target, end);
+            if end + skip == target.len() || skip >= MIN_SKIP {
+                break;
+            }
+            end += skip;
+        }
+
+        write_varint(delta, skip as u64);
+        write_varint(delta, (end - start) as u64);
+        let base_end = end.min(base.len());
+        let changed = &target[start..end];
+        if start < base_end {
+            delta.extend(
+                changed
+                    .iter()
+                    .zip(&base[start..base_end])
+                    .map(|(target, base)| target ^ base),
+            );
+        }
+        delta.extend_from_slice(&changed[base_end.saturating_sub(start)..]);
+        pos = end;
+    }
+}
+
+// Turns `save` from the base `delta` was encoded against into its target.
+pub fn apply_delta(save: &mut Vec<u8>, delta: &[u8]) -> Result<(), ReadError> {
+    let mut body = delta;
+    let mut pos = 0;
+    let base_len = body.read_varint_at(&mut pos)?;
+    if base_len != save.len() as u64 {
+        return Err(ReadErrorKind::LengthMismatch {
+            expected: base_len,
+            found: save.len() as u64,
+        }
+        .into());
+    }
+    let target_len = usize::try_from(body.read_varint_at(&mut pos)?)
+        .map_err(|_| ReadErrorKind::SaveTooLarge.at(pos))?;
+
+    // Check the runs before touching `save`, so it's left as-is if the delta is invalid
+    let runs_pos = pos;
+    let mut save_pos = 0_usize;
+    while pos < delta.len() as u64 {
+        let run_pos = pos;
+        let skip = body.read_varint_at(&mut pos)?;
+        let len = body.read_varint_at(&mut pos)?;
+        save_pos = usize::try_from(skip)
+            .ok()
+            .zip(usize::try_from(len).ok())
+            .and_then(|(skip, len)| save_pos.checked_add(skip)?.checked_add(len))
+            .filter(|end| *end <= target_len)
+            .ok_or(ReadErrorKind::InvalidDelta.at(run_pos))?;
+        body.check_len(pos, len)?;
+        pos += len;
+    }
+
+    save.resize(target_len, 0);
+    pos = runs_pos;
+    save_pos = 0;
+    while pos < delta.len() as u64 {
+        save_pos += body.read_varint_at(&mut pos)? as usize;
+        let len = body.read_varint_at(&mut pos)? as usize;
+        let changed_start = pos as usize;
+        for (byte, changed) in save[save_pos..save_pos + len]
+            .iter_mut()
+            .zip(&delta[changed_start..changed_start + len])
+        {
+            *byte ^= changed;
+        }
+        save_pos += len;
+        pos += len as u64;
+    }
+    Ok(())
+}
+
+// Restores a snapshot into `save` from the keyframe it was derived from, applying a chain of
+// deltas, each encoded against the snapshot before it.
+pub fn restore_from_deltas<'a>(
+    keyframe: &[u8],
+    deltas: impl IntoIterator<Item = &'a [u8]>,
+    save: &mut Vec<u8>,
+) -> Result<(), ReadError> {
+    save.clear();
+    save.extend_from_slice(keyframe);
+    for delta in deltas {
+        apply_delta(save, delta)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(base: &[u8], target: &[u8]) -> Vec<u8> {
+        let mut delta = Vec::new();
+        encode_delta(base, target, &mut delta);
+        let mut save = base.to_vec();
+        apply_delta(&mut save, &delta).unwrap();
+        assert_eq!(save, target);
+        delta
+    }
+
+    #[test]
+    fn identical() {
+        let base = (0..100).collect::<Vec<u8>>();
+        assert_eq!(round_trip(&base, &base), [100, 100]);
+        assert_eq!(round_trip(&[], &[]), [0, 0]);
+    }
+
+    #[test]
+    fn different_lengths() {
+        let base = (0..100).collect::<Vec<u8>>();
+        let mut shorter = base[..37].to_vec();
+        shorter[3] ^= 1;
+        round_trip(&base, &shorter);
+        assert_eq!(round_trip(&base, &base[..37]), [100, 37]);
+        round_trip(&base, &[]);
+
+        let mut longer = base.clone();
+        longer.extend(1..=50);
+        round_trip(&base, &longer);
+        round_trip(&[], &longer);
+        // Zeros past the base's end are unchanged
+        longer.resize(200, 0);
+        assert_eq!(round_trip(&base, &longer)[..5], [100, 0xC8, 1, 100, 50]);
+    }
+
+    #[test]
+    fn runs_across_chunks() {
+        let base = vec![0; 64];
+        for start in 0..20 {
+            for len in 1..20 {
+                let mut target = base.clone();
+                target[start..start + len].fill(0xAA);
+                let delta = round_trip(&base, &target);
+                // A single run
+                assert_eq!(delta[2..4], [start as u8, len as u8]);
+                assert_eq!(delta.len(), 4 + len);
+            }
+        }
+
+        // Unchanged runs shorter than `MIN_SKIP` are merged into the changed bytes around them
+        let mut target = base.clone();
+        target[7] = 1;
+        target[11] = 2;
+        target[16] = 3;
+        assert_eq!(
+            round_trip(&base, &target)[2..],
+            [7, 5, 1, 0, 0, 0, 2, 4, 1, 3]
+        );
+
+        // Zero runs starting inside the base and ending past it
+        let base = [1; 13];
+        for end in 13 + MIN_SKIP..40 {
+            let mut target = base.to_vec();
+            target[9..].fill(0);
+            target.resize(end, 0);
+            target.push(5);
+            assert_eq!(
+                round_trip(&base, &target)[2..],
+                [9, 4, 1, 1, 1, 1, end as u8 - 13, 1, 5]
+            );
+        }
+    }
+
+    #[test]
+    fn corrupt_deltas() {
+        let base = (0..100).collect::<Vec<u8>>();
+        let mut target = base.clone();
+        target[10..20].fill(0);
+        target.extend_from_slice(&[1; 10]);
+        let delta = round_trip(&base, &target);
+
+        let mut corrupt = vec![
+            // Wrong base length
+            [&[99][..], &delta[1..]].concat(),
+            // Run past the target's end
+            [&delta[..2], &[105, 6], &[0; 6]].concat(),
+            // Invalid varint
+            [&delta[..2], &[0x80, 0x80]].concat(),
+        ];
+        // Truncating the delta between runs leaves a valid one
+        assert_eq!(delta.len(), 26);
+        corrupt.extend(
+            (0..delta.len())
+                .filter(|len| ![2, 14].contains(len))
+                .map(|len| delta[..len].to_vec()),
+        );
+        for delta in corrupt {
+            let mut save = base.clone();
+            assert!(apply_delta(&mut save, &delta).is_err());
+            assert_eq!(save, base);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use super::{SaveInspector, SaveNode, SaveValue};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldChange {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Clone, Debug)]
+pub struct FieldDiff<'a> {
+    /// Dot-separated field names leading to the value, i.e. `arm9.regs.r13`; empty for the root.
+    pub path: String,
+    pub change: FieldChange,
+    pub old: Option<SaveValue<'a>>,
+    pub new: Option<SaveValue<'a>>,
+}
+
+// Compares two persistent savestates field by field, matching fields by name rather than by
+// position. Differences are reported in the storage order of `old`, followed by fields that only
+// exist in `new`; added or removed structs are reported as a whole.
+pub fn diff_saves<'a>(old: &'a SaveInspector, new: &'a SaveInspector) -> Vec<FieldDiff<'a>> {
+    let mut diffs = Vec::new();
+    diff_nodes(
+        old,
+        new,
+        &old.root(),
+        &new.root(),
+        &mut String::new(),
+        &mut diffs,
+    );
+    diffs
+}
+
+fn diff_nodes<'a>(
+    old: &'a SaveInspector,
+    new: &'a SaveInspector,
+    old_node: &SaveNode,
+    new_node: &SaveNode,
+    path: &mut String,
+    diffs: &mut Vec<FieldDiff<'a>>,
+) {
+    let (Some(old_fields), Some(new_fields)) = (&old_node.fields, &new_node.fields) else {
+        let (old_value, new_value) = (old.value(old_node), new.value(new_node));
+        if old_value != new_value || old_node.fields.is_some() != new_node.fields.is_some() {
+            diffs.push(FieldDiff {
+                path: path.clone(),
+                change: FieldChange::Changed,
+                old: Some(old_value),
+                new: Some(new_value),
+            });
+        }
+        return;
+    };
+
+    let path_len = path.len();
+    let push_field = |path: &mut String, name: &str| {
+        if path_len != 0 {
+            path.push('.');
+        }
+        path.push_str(name);
+    };
+
+    for old_field in old_fields {
+        push_field(path, &old_field.name);
+        match new_fields.iter().find(|field| field.name == old_field.name) {
+            Some(new_field) => {
+                diff_nodes(old, new, &old_field.value, &new_field.value, path, diffs)
+            }
+            None => diffs.push(FieldDiff {
+                path: path.clone(),
+                change: FieldChange::Removed,
+                old: Some(old.value(&old_field.value)),
+                new: None,
+            }),
+        }
+        path.truncate(path_len);
+    }
+
+    for new_field in new_fields {
+        if old_fields.iter().any(|field| field.name == new_field.name) {
+            continue;
+        }
+        push_field(path, &new_field.name);
+        diffs.push(FieldDiff {
+            path: path.clone(),
+            change: FieldChange::Added,
+            old: None,
+            new: Some(new.value(&new_field.value)),
+        });
+        path.truncate(path_len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{write_persistent, Savestate, Storable, WriteOptions, WriteSavestate};
+
+    #[derive(Savestate)]
+    struct Inner {
+        x: u16,
+        y: u16,
+    }
+
+    #[derive(Savestate)]
+    struct Old {
+        a: u32,
+        inner: Inner,
+        removed: u8,
+    }
+
+    #[derive(Savestate)]
+    struct New {
+        a: u32,
+        inner: Inner,
+        added: u8,
+    }
+
+    fn save<T: Storable>(value: &mut T) -> Vec<u8> {
+        write_persistent(WriteOptions::default(), |writer| writer.store(value))
+    }
+
+    // Returns the path, kind and displayed old and new values of each difference.
+    fn summarize(diffs: &[FieldDiff]) -> Vec<(String, FieldChange, String, String)> {
+        let display = |value: Option<SaveValue>| value.map_or(String::new(), |v| v.to_string());
+        diffs
+            .iter()
+            .map(|diff| {
+                (
+                    diff.path.clone(),
+                    diff.change,
+                    display(diff.old),
+                    display(diff.new),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn changed_field() {
+        let mut old = Old {
+            a: 1,
+            inner: Inner { x: 2, y: 3 },
+            removed: 4,
+        };
+        let old_save = save(&mut old);
+        old.inner.x = 7;
+        let new_save = save(&mut old);
+
+        let (old, new) = (
+            SaveInspector::new(&old_save).unwrap(),
+            SaveInspector::new(&new_save).unwrap(),
+        );
+        assert_eq!(
+            summarize(&diff_saves(&old, &new)),
+            [(
+                "inner.x".to_string(),
+                FieldChange::Changed,
+                "0x0002 (2)".to_string(),
+                "0x0007 (7)".to_string(),
+            )]
+        );
+        assert!(diff_saves(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn added_and_removed_fields() {
+        let old_save = save(&mut Old {
+            a: 1,
+            inner: Inner { x: 2, y: 3 },
+            removed: 4,
+        });
+        let new_save = save(&mut New {
+            a: 5,
+            inner: Inner { x: 2, y: 3 },
+            added: 6,
+        });
+
+        let (old, new) = (
+            SaveInspector::new(&old_save).unwrap(),
+            SaveInspector::new(&new_save).unwrap(),
+        );
+        assert_eq!(
+            summarize(&diff_saves(&old, &new)),
+            [
+                (
+                    "a".to_string(),
+                    FieldChange::Changed,
+                    "0x00000001 (1)".to_string(),
+                    "0x00000005 (5)".to_string(),
+                ),
+                (
+                    "removed".to_string(),
+                    FieldChange::Removed,
+                    "0x04 (4)".to_string(),
+                    String::new(),
+                ),
+                (
+                    "added".to_string(),
+                    FieldChange::Added,
+                    String::new(),
+                    "0x06 (6)".to_string(),
+                ),
+            ]
+        );
+    }
+}
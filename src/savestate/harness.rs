@@ -0,0 +1,614 @@
+// Test helpers for `Storable`/`Loadable` implementations, meant to be called from the tests of
+// crates deriving `Savestate` through the `testing` feature.
+
+use super::{
+    checksum::crc32, Loadable, LoadableInPlace, PersistentReadSavestate, PersistentWriteSavestate,
+    ReadSavestate, SaveInfo, Storable, StreamReadSavestate, StreamWriteSavestate,
+    TextReadSavestate, TextWriteSavestate, TransientReadSavestate, TransientWriteSavestate,
+    WriteError, WriteOptions, WriteSavestate,
+};
+use core::fmt::{Debug, Display};
+use std::io::Cursor;
+
+// Every combination of container flags, with both the fastest and the smallest compression.
+const WRITE_OPTIONS: [WriteOptions; 5] = [
+    WriteOptions {
+        checksum: false,
+        compression: None,
+    },
+    WriteOptions {
+        checksum: true,
+        compression: None,
+    },
+    WriteOptions {
+        checksum: false,
+        compression: Some(1),
+    },
+    WriteOptions {
+        checksum: true,
+        compression: Some(1),
+    },
+    WriteOptions {
+        checksum: true,
+        compression: Some(9),
+    },
+];
+
+// Info stored in the savestates written by tests, which is fixed so that they can be compared.
+pub(crate) fn test_info() -> SaveInfo {
+    SaveInfo {
+        emu_id: "test".to_string(),
+        emu_version: 0,
+        timestamp: 0,
+    }
+}
+
+// Writes a persistent savestate through `store`, which is also used to build savestates by hand in
+// tests.
+pub(crate) fn write_persistent(
+    options: WriteOptions,
+    store: impl FnOnce(&mut PersistentWriteSavestate) -> Result<(), WriteError>,
+) -> Vec<u8> {
+    let mut save = Vec::new();
+    let mut writer = PersistentWriteSavestate::new(&mut save, &test_info(), options)
+        .expect("couldn't create persistent savestate");
+    store(&mut writer).expect("couldn't store value in persistent savestate");
+    writer
+        .finish()
+        .expect("couldn't finish persistent savestate");
+    save
+}
+
+fn store_stream<T: Storable>(value: &mut T, options: WriteOptions) -> Vec<u8> {
+    let mut writer = StreamWriteSavestate::new(Cursor::new(Vec::new()), &test_info(), options)
+        .expect("couldn't create stream savestate");
+    writer
+        .store(value)
+        .expect("couldn't store value in stream savestate");
+    writer
+        .finish()
+        .expect("couldn't finish stream savestate")
+        .into_inner()
+}
+
+fn store_text<T: Storable>(value: &mut T) -> String {
+    let mut save = String::new();
+    let mut writer = TextWriteSavestate::new(&mut save, &test_info());
+    writer
+        .store(value)
+        .expect("couldn't store value in text savestate");
+    writer.finish().expect("couldn't finish text savestate");
+    save
+}
+
+fn store_transient<T: Storable>(value: &mut T) -> Vec<u8> {
+    let mut save = Vec::new();
+    let Ok(()) = TransientWriteSavestate::new(&mut save).store(value);
+    save
+}
+
+// Checks that loading the savestate returned by `new_reader` through both `Loadable` and
+// `LoadableInPlace` yields a value equal to `value`, which `store` serializes back to `save`.
+fn check_loads<T, S, V>(
+    format: &str,
+    value: &T,
+    new_target: &mut impl FnMut() -> T,
+    mut new_reader: impl FnMut() -> Result<S, S::Error>,
+    save: &V,
+    mut store: impl FnMut(&mut T) -> V,
+) where
+    T: Loadable + LoadableInPlace + PartialEq + Debug,
+    S: ReadSavestate,
+    S::Error: Display,
+    V: PartialEq,
+{
+    let mut loaded: T = new_reader()
+        .and_then(|mut reader| reader.load())
+        .unwrap_or_else(|err| panic!("couldn't load {format} savestate: {err}"));
+    assert_eq!(
+        &loaded, value,
+        "value loaded from {format} savestate differs"
+    );
+    assert!(
+        store(&mut loaded) == *save,
+        "{format} savestate of loaded value differs"
+    );
+
+    let mut target = new_target();
+    new_reader()
+        .and_then(|mut reader| reader.load_into(&mut target))
+        .unwrap_or_else(|err| panic!("couldn't load {format} savestate in place: {err}"));
+    assert_eq!(
+        &target, value,
+        "value loaded in place from {format} savestate differs"
+    );
+    assert!(
+        store(&mut target) == *save,
+        "{format} savestate of value loaded in place differs"
+    );
+}
+
+// Stores `value` in persistent savestates with every combination of `WriteOptions`, as well as in
+// stream, text and transient savestates, then checks that loading them back through both
+// `Loadable` and `LoadableInPlace` yields an equal value, which is serialized to identical
+// savestates. In-place loads go into values returned by `new_target`, which should differ from
+// `value` to catch fields that aren't loaded.
+pub fn check_round_trip<T>(value: &mut T, mut new_target: impl FnMut() -> T)
+where
+    T: Storable + Loadable + LoadableInPlace + PartialEq + Debug,
+{
+    for options in WRITE_OPTIONS {
+        let persistent = write_persistent(options, |writer| writer.store(value));
+        check_loads(
+            &format!("persistent ({options:?})"),
+            value,
+            &mut new_target,
+            || PersistentReadSavestate::new(&persistent),
+            &persistent,
+            |value| write_persistent(options, |writer| writer.store(value)),
+        );
+
+        // Compressed savestates can't be streamed
+        if options.compression.is_none() {
+            assert!(
+                store_stream(value, options) == persistent,
+                "stream savestate differs from persistent one ({options:?})"
+            );
+            check_loads(
+                &format!("stream ({options:?})"),
+                value,
+                &mut new_target,
+                || StreamReadSavestate::new(Cursor::new(&persistent[..])),
+                &persistent,
+                |value| write_persistent(options, |writer| writer.store(value)),
+            );
+        }
+    }
+
+    let text = store_text(value);
+    check_loads(
+        "text",
+        value,
+        &mut new_target,
+        || TextReadSavestate::new(&text),
+        &text,
+        store_text,
+    );
+
+    let transient = store_transient(value);
+    check_loads(
+        "transient",
+        value,
+        &mut new_target,
+        // The savestate was produced by a `TransientWriteSavestate` for the same type
+        || Ok(unsafe { TransientReadSavestate::new(&transient) }),
+        &transient,
+        store_transient,
+    );
+}
+
+// xorshift64*, to keep mutations reproducible from a seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound.max(1) as u64) as usize
+    }
+}
+
+fn mutate(save: &mut Vec<u8>, rng: &mut Rng) {
+    for _ in 0..1 + rng.below(4) {
+        let pos = rng.below(save.len());
+        match rng.below(6) {
+            0 if !save.is_empty() => save[pos] ^= 1 << rng.below(8),
+            1 if !save.is_empty() => {
+                save[pos] = [0, 0x7F, 0x80, 0xFF, rng.next() as u8][rng.below(5)];
+            }
+            2 => save.truncate(pos),
+            3 => {
+                let len = rng.below(16);
+                save.splice(pos..pos, (0..len).map(|_| rng.next() as u8));
+            }
+            4 => {
+                let end = (pos + rng.below(16)).min(save.len());
+                save.drain(pos..end);
+            }
+            _ => {
+                // Large varints and lengths
+                let bytes = rng.next().to_le_bytes();
+                let end = (pos + 8).min(save.len());
+                save.splice(pos..end, bytes);
+            }
+        }
+    }
+}
+
+// Feeds `iterations` randomly mutated savestates of `value` in each persistent container format, as
+// well as in the text format, to both `Loadable` and `LoadableInPlace` implementations through
+// every reader accepting them, which must return errors instead of panicking; targets for
+// in-place loads are returned by `new_target`. Mutations are derived from `seed`. Half of the
+// mutated checksummed savestates get their checksum fixed up, so that the body still gets loaded.
+pub fn fuzz_load<T>(value: &mut T, mut new_target: impl FnMut() -> T, iterations: usize, seed: u64)
+where
+    T: Storable + Loadable + LoadableInPlace,
+{
+    fn load_all<T: Loadable + LoadableInPlace, S: ReadSavestate>(
+        mut new_reader: impl FnMut() -> Result<S, S::Error>,
+        new_target: &mut impl FnMut() -> T,
+    ) {
+        if let Ok(mut reader) = new_reader() {
+            let _ = reader.load::<T>();
+        }
+        if let Ok(mut reader) = new_reader() {
+            let _ = reader.load_into(&mut new_target());
+        }
+    }
+
+    let mut rng = Rng(seed | 1);
+    let mut save = Vec::new();
+    for options in WRITE_OPTIONS {
+        let original = write_persistent(options, |writer| writer.store(value));
+        for _ in 0..iterations {
+            save.clear();
+            save.extend_from_slice(&original);
+            mutate(&mut save, &mut rng);
+            if options.checksum && save.len() >= 4 && rng.below(2) == 0 {
+                let checksum_start = save.len() - 4;
+                let checksum = crc32(&save[..checksum_start]);
+                save[checksum_start..].copy_from_slice(&checksum.to_le_bytes());
+            }
+
+            load_all(|| PersistentReadSavestate::new(&save), &mut new_target);
+            load_all(
+                || StreamReadSavestate::new(Cursor::new(&save[..])),
+                &mut new_target,
+            );
+        }
+    }
+
+    let original = store_text(value).into_bytes();
+    for _ in 0..iterations {
+        save.clear();
+        save.extend_from_slice(&original);
+        mutate(&mut save, &mut rng);
+        let text = String::from_utf8_lossy(&save);
+        load_all(|| TextReadSavestate::new(&text), &mut new_target);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BoxedByteSlice, Fifo, Savestate};
+
+    // Compares floats bitwise, so that NaN payloads have to round-trip too.
+    #[derive(Savestate, Clone, Copy)]
+    struct Floats(f32, f64);
+
+    impl PartialEq for Floats {
+        fn eq(&self, other: &Self) -> bool {
+            self.0.to_bits() == other.0.to_bits() && self.1.to_bits() == other.1.to_bits()
+        }
+    }
+
+    impl Debug for Floats {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            write!(
+                f,
+                "Floats({:#x}, {:#x})",
+                self.0.to_bits(),
+                self.1.to_bits()
+            )
+        }
+    }
+
+    #[derive(Savestate, Clone, PartialEq, Debug)]
+    struct Primitives {
+        a: u8,
+        b: i8,
+        c: u16,
+        d: i32,
+        e: u64,
+        f: i128,
+        g: usize,
+        h: isize,
+        i: bool,
+        j: [bool; 3],
+        floats: Floats,
+    }
+
+    #[test]
+    fn primitives() {
+        for mut value in [0, 0x80, usize::MAX] {
+            check_round_trip(&mut value, || 1);
+        }
+        for mut value in [isize::MIN, -1, isize::MAX] {
+            check_round_trip(&mut value, || 0);
+        }
+        check_round_trip(&mut -3_i8, || 0);
+        check_round_trip(&mut 0x1F_u16, || 0);
+        check_round_trip(&mut true, || false);
+        check_round_trip(&mut 1.5_f32, || 0.0);
+        for mut floats in [
+            Floats(f32::NAN, -f64::NAN),
+            Floats(
+                f32::from_bits(0xFFC0_1234),
+                f64::from_bits(0x7FF0_0000_0000_0001),
+            ),
+            Floats(-0.0, f64::INFINITY),
+        ] {
+            check_round_trip(&mut floats, || Floats(0.0, 0.0));
+        }
+
+        let mut value = Primitives {
+            a: 0xFF,
+            b: -0x80,
+            c: 0x1234,
+            d: -2,
+            e: u64::MAX,
+            f: i128::MIN,
+            g: usize::MAX,
+            h: isize::MIN,
+            i: true,
+            j: [false, true, true],
+            floats: Floats(f32::NAN, 1.0),
+        };
+        let new_target = || Primitives {
+            a: 0,
+            b: 0,
+            c: 0,
+            d: 0,
+            e: 0,
+            f: 0,
+            g: 0,
+            h: 0,
+            i: false,
+            j: [false; 3],
+            floats: Floats(0.0, 0.0),
+        };
+        check_round_trip(&mut value, new_target);
+        fuzz_load(&mut value, new_target, 300, 1);
+    }
+
+    #[derive(Savestate, Clone, PartialEq, Debug)]
+    struct Collections {
+        values: Vec<u32>,
+        nested: Vec<Vec<u8>>,
+        boxed: Box<[u16]>,
+        strings: Box<[String]>,
+        name: String,
+    }
+
+    #[test]
+    fn collections() {
+        let mut value = Collections {
+            values: (0..100).map(|i| i * 0x1_0001).collect(),
+            nested: vec![vec![], vec![1, 2, 3], vec![0; 0x100]],
+            boxed: (0..10).collect(),
+            strings: vec!["".to_string(), "\"quoted\"\n".to_string(), "é".to_string()].into(),
+            name: "name".to_string(),
+        };
+        let new_target = || Collections {
+            values: vec![7; 200],
+            nested: vec![vec![5]],
+            boxed: Box::new([]),
+            strings: vec!["other".to_string(); 5].into(),
+            name: String::with_capacity(100),
+        };
+        check_round_trip(&mut value, new_target);
+        fuzz_load(&mut value, new_target, 300, 2);
+        check_round_trip(&mut String::new(), || "x".to_string());
+    }
+
+    // Neither `Fifo` nor `BoxedByteSlice` implement `PartialEq` and `Debug`, so compare their
+    // contents instead.
+    #[derive(Savestate, Clone)]
+    struct Containers {
+        fifo: Fifo<u16, 8>,
+        bytes: BoxedByteSlice,
+    }
+
+    impl Containers {
+        fn contents(&self) -> (Vec<u16>, &[u8]) {
+            let mut fifo = self.fifo;
+            let values = core::iter::from_fn(|| fifo.read()).collect();
+            (values, &self.bytes)
+        }
+    }
+
+    impl PartialEq for Containers {
+        fn eq(&self, other: &Self) -> bool {
+            self.contents() == other.contents()
+        }
+    }
+
+    impl Debug for Containers {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            self.contents().fmt(f)
+        }
+    }
+
+    #[test]
+    fn containers() {
+        let mut fifo = Fifo::new();
+        for i in 0..14 {
+            fifo.write(i).unwrap();
+            if i % 2 == 0 {
+                fifo.read().unwrap();
+            }
+        }
+        let mut bytes = BoxedByteSlice::new_zeroed(0x1000);
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = ((i * 7) >> 3) as u8;
+        }
+        let mut value = Containers { fifo, bytes };
+        let new_target = || {
+            let mut fifo = Fifo::new();
+            fifo.write(5).unwrap();
+            Containers {
+                fifo,
+                bytes: BoxedByteSlice::new_zeroed(0x1000),
+            }
+        };
+        check_round_trip(&mut value, new_target);
+        fuzz_load(&mut value, new_target, 300, 3);
+    }
+
+    #[derive(Savestate)]
+    struct OldNames {
+        older_name: u32,
+        #[savestate(rename = "stored")]
+        renamed: u8,
+    }
+
+    #[derive(Savestate, Clone, PartialEq, Debug)]
+    struct Names {
+        #[savestate(alias = "old_name", alias = "older_name")]
+        value: u32,
+        #[savestate(rename = "stored")]
+        renamed: u8,
+        #[load(default)]
+        added: u16,
+        #[load(default = "3")]
+        added_with_value: u8,
+    }
+
+    #[derive(Savestate, Clone, PartialEq, Debug)]
+    #[repr(u8)]
+    enum Tagged {
+        #[savestate(tag = 2)]
+        C {
+            x: u16,
+        } = 5,
+        Z = 7,
+        A = 0,
+        #[savestate(tag = 1)]
+        B(Names),
+    }
+
+    // Version 0 of `Versioned`
+    #[derive(Savestate)]
+    struct Unversioned {
+        reg: u16,
+        other: u8,
+    }
+
+    #[derive(Savestate, Clone, PartialEq, Debug)]
+    #[savestate(version = 2)]
+    #[load(
+        migrate_from_0 = "{ save.start_field(b\"reg\")?; let reg: u16 = save.load()?; \
+                          self.lo = reg as u8; self.hi = (reg >> 8) as u8; }",
+        migrate_from_1 = "self.other += 100"
+    )]
+    struct Versioned {
+        #[load(default)]
+        lo: u8,
+        #[load(default)]
+        hi: u8,
+        other: u8,
+    }
+
+    // Loads `value` as a `U` from persistent savestates with every combination of `WriteOptions`,
+    // through both `PersistentReadSavestate` and `StreamReadSavestate`.
+    fn check_migration<T: Storable, U: Loadable + LoadableInPlace + PartialEq + Debug>(
+        value: &mut T,
+        expected: &U,
+        mut new_target: impl FnMut() -> U,
+    ) {
+        for options in WRITE_OPTIONS {
+            let save = write_persistent(options, |writer| writer.store(value));
+            let mut reader = PersistentReadSavestate::new(&save).unwrap();
+            assert_eq!(&reader.load::<U>().unwrap(), expected);
+            if options.compression.is_none() {
+                let mut reader = StreamReadSavestate::new(Cursor::new(&save[..])).unwrap();
+                let mut target = new_target();
+                reader.load_into(&mut target).unwrap();
+                assert_eq!(&target, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn derived() {
+        let names = Names {
+            value: 0x1234_5678,
+            renamed: 2,
+            added: 0xFFFF,
+            added_with_value: 4,
+        };
+        let new_names = || Names {
+            value: 0,
+            renamed: 0,
+            added: 0,
+            added_with_value: 0,
+        };
+        check_round_trip(&mut names.clone(), new_names);
+        check_migration(
+            &mut OldNames {
+                older_name: 5,
+                renamed: 2,
+            },
+            &Names {
+                value: 5,
+                renamed: 2,
+                added: 0,
+                added_with_value: 3,
+            },
+            new_names,
+        );
+
+        let mut tagged = [
+            Tagged::C { x: 0x1234 },
+            Tagged::Z,
+            Tagged::A,
+            Tagged::B(names.clone()),
+        ];
+        check_round_trip(&mut tagged, || {
+            [
+                Tagged::A,
+                Tagged::C { x: 0 },
+                Tagged::B(new_names()),
+                Tagged::Z,
+            ]
+        });
+        fuzz_load(
+            &mut tagged,
+            || [Tagged::Z, Tagged::Z, Tagged::Z, Tagged::Z],
+            300,
+            4,
+        );
+
+        let mut versioned = Versioned {
+            lo: 1,
+            hi: 2,
+            other: 3,
+        };
+        let new_versioned = || Versioned {
+            lo: 0,
+            hi: 0,
+            other: 0,
+        };
+        check_round_trip(&mut versioned, new_versioned);
+        fuzz_load(&mut versioned, new_versioned, 300, 5);
+        check_migration(
+            &mut Unversioned {
+                reg: 0x1234,
+                other: 1,
+            },
+            // Migrations from older versions are chained
+            &Versioned {
+                lo: 0x34,
+                hi: 0x12,
+                other: 101,
+            },
+            new_versioned,
+        );
+    }
+}
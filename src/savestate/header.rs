@@ -0,0 +1,215 @@
+use super::{ReadError, ReadErrorKind, WriteError, WriteErrorKind};
+use std::{
+    io::{self, Read},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub(super) const MAGIC: [u8; 8] = *b"EMUSTATE";
+pub(super) const FORMAT_VERSION: u16 = 2;
+// Version 1 is the body format from before headers were added: it stores offsets as `u32`s and
+// field counts as `u8`s instead of using varints, doesn't tag raw values with their types, stores
+// pointer-sized integers as 32-bit ones and doesn't record struct versions in field tables. Bodies
+// saved without any header can be loaded through the readers' `new_headerless` constructors.
+pub(super) const MIN_FORMAT_VERSION: u16 = 1;
+
+// A CRC-32 of the header and body follows the body.
+pub(super) const FLAG_CHECKSUM: u16 = 1 << 0;
+// The body is compressed, and prefixed with its 64-bit uncompressed length.
+pub(super) const FLAG_COMPRESSED: u16 = 1 << 1;
+const KNOWN_FLAGS: u16 = FLAG_CHECKSUM | FLAG_COMPRESSED;
+
+// Magic, format version, flags, emulator version, timestamp and emulator ID length.
+const FIXED_LEN: usize = 8 + 2 + 2 + 4 + 8 + 2;
+
+// Identifies the emulator that produced a persistent savestate; empty for headerless ones.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SaveInfo {
+    pub emu_id: String,
+    pub emu_version: u32,
+    /// Creation time, in seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl SaveInfo {
+    pub fn new(emu_id: impl Into<String>, emu_version: u32) -> Self {
+        SaveInfo {
+            emu_id: emu_id.into(),
+            emu_version,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs()),
+        }
+    }
+}
+
+pub(super) struct Header {
+    pub format_version: u16,
+    pub flags: u16,
+    pub info: SaveInfo,
+}
+
+impl Header {
+    pub fn write(&self, save: &mut Vec<u8>) -> Result<(), WriteError> {
+        let emu_id_len =
+            u16::try_from(self.info.emu_id.len()).map_err(|_| WriteErrorKind::EmuIdTooLong)?;
+        save.reserve(FIXED_LEN + self.info.emu_id.len());
+        save.extend_from_slice(&MAGIC);
+        save.extend_from_slice(&self.format_version.to_le_bytes());
+        save.extend_from_slice(&self.flags.to_le_bytes());
+        save.extend_from_slice(&self.info.emu_version.to_le_bytes());
+        save.extend_from_slice(&self.info.timestamp.to_le_bytes());
+        save.extend_from_slice(&emu_id_len.to_le_bytes());
+        save.extend_from_slice(self.info.emu_id.as_bytes());
+        Ok(())
+    }
+
+    // Returns the parsed header and its length in bytes.
+    pub fn parse(mut save: &[u8]) -> Result<(Self, usize), ReadError> {
+        Self::read(&mut save)
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> Result<(Self, usize), ReadError> {
+        let mut fixed = [0; FIXED_LEN];
+        read_exact(reader, &mut fixed)?;
+        if fixed[..8] != MAGIC {
+            return Err(ReadErrorKind::InvalidMagic.into());
+        }
+
+        let read_u16 = |pos: usize| u16::from_le_bytes([fixed[pos], fixed[pos + 1]]);
+        let format_version = read_u16(8);
+        if !(MIN_FORMAT_VERSION..=FORMAT_VERSION).contains(&format_version) {
+            return Err(ReadErrorKind::UnsupportedVersion(format_version).into());
+        }
+        let flags = read_u16(10);
+        if flags & !KNOWN_FLAGS != 0 {
+            return Err(ReadErrorKind::InvalidHeader.into());
+        }
+        let emu_version = u32::from_le_bytes(fixed[12..16].try_into().unwrap());
+        let timestamp = u64::from_le_bytes(fixed[16..24].try_into().unwrap());
+        let emu_id_len = read_u16(24) as usize;
+
+        let len = FIXED_LEN + emu_id_len;
+        let mut emu_id = vec![0; emu_id_len];
+        read_exact(reader, &mut emu_id)?;
+        let emu_id = String::from_utf8(emu_id).map_err(|_| ReadErrorKind::InvalidHeader)?;
+
+        Ok((
+            Header {
+                format_version,
+                flags,
+                info: SaveInfo {
+                    emu_id,
+                    emu_version,
+                    timestamp,
+                },
+            },
+            len,
+        ))
+    }
+}
+
+pub(super) fn read_error(err: io::Error) -> ReadError {
+    ReadErrorKind::Io(err.kind()).into()
+}
+
+// Like `Read::read_exact`, but reports how many bytes were available if the end of the input is
+// reached early.
+pub(super) fn read_exact<R: Read>(reader: &mut R, bytes: &mut [u8]) -> Result<(), ReadError> {
+    let mut read_len = 0;
+    while read_len < bytes.len() {
+        match reader.read(&mut bytes[read_len..]) {
+            Ok(0) => {
+                return Err(ReadErrorKind::UnexpectedEof {
+                    expected: bytes.len() as u64,
+                    found: read_len as u64,
+                }
+                .into())
+            }
+            Ok(len) => read_len += len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(read_error(err)),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> Vec<u8> {
+        let mut save = Vec::new();
+        Header {
+            format_version: FORMAT_VERSION,
+            flags: FLAG_CHECKSUM,
+            info: SaveInfo {
+                emu_id: "test".to_string(),
+                emu_version: 3,
+                timestamp: 4,
+            },
+        }
+        .write(&mut save)
+        .unwrap();
+        save
+    }
+
+    fn parse_err(save: &[u8]) -> ReadErrorKind {
+        Header::parse(save).err().unwrap().kind
+    }
+
+    #[test]
+    fn round_trip() {
+        let save = header();
+        let (header, len) = Header::parse(&save).unwrap();
+        assert_eq!(len, save.len());
+        assert_eq!(header.format_version, FORMAT_VERSION);
+        assert_eq!(header.flags, FLAG_CHECKSUM);
+        assert_eq!(
+            header.info,
+            SaveInfo {
+                emu_id: "test".to_string(),
+                emu_version: 3,
+                timestamp: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_magic() {
+        let mut save = header();
+        save[0] ^= 1;
+        assert_eq!(parse_err(&save), ReadErrorKind::InvalidMagic);
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let mut save = header();
+        for version in [0, FORMAT_VERSION + 1] {
+            save[8..10].copy_from_slice(&version.to_le_bytes());
+            assert_eq!(parse_err(&save), ReadErrorKind::UnsupportedVersion(version));
+        }
+    }
+
+    #[test]
+    fn unknown_flags() {
+        let mut save = header();
+        save[10..12].copy_from_slice(&(KNOWN_FLAGS + 1).to_le_bytes());
+        assert_eq!(parse_err(&save), ReadErrorKind::InvalidHeader);
+    }
+
+    #[test]
+    fn invalid_emu_id() {
+        let mut save = header();
+        *save.last_mut().unwrap() = 0xFF;
+        assert_eq!(parse_err(&save), ReadErrorKind::InvalidHeader);
+
+        let save = header();
+        assert_eq!(
+            parse_err(&save[..save.len() - 1]),
+            ReadErrorKind::UnexpectedEof {
+                expected: 4,
+                found: 3,
+            }
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use super::{
+    read::{decode_container, parse_struct},
+    ReadError, SaveInfo, ValueKind, ValueType,
+};
+use core::fmt;
+use std::borrow::Cow;
+
+// Deeper values are reported as opaque, to bound recursion on corrupted saves.
+const MAX_DEPTH: usize = 64;
+// Leading bytes shown when displaying untyped values.
+const MAX_PREVIEW_BYTES: usize = 16;
+
+#[derive(Clone, Debug)]
+pub struct SaveNode {
+    /// Position of the value inside the savestate body, right after the header.
+    pub offset: usize,
+    pub len: usize,
+    /// The value's fields in storage order, or `None` if it doesn't look like a struct.
+    pub fields: Option<Vec<SaveField>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SaveField {
+    pub name: String,
+    pub value: SaveNode,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SaveValue<'a> {
+    /// The value's type, if it's a single tagged raw value (not recorded in format version 1).
+    pub ty: Option<ValueType>,
+    /// The value's contents, excluding its type tag.
+    pub bytes: &'a [u8],
+}
+
+impl<'a> fmt::Display for SaveValue<'a> {
+    // Integers are shown in hexadecimal and decimal, assuming little-endian storage; untyped values
+    // of integer sizes are assumed to be unsigned integers, and shown as lists of bytes otherwise.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut value_bytes = [0; 16];
+        let len = self.bytes.len();
+        match self.ty {
+            Some(ValueType {
+                kind: ValueKind::Float,
+                ..
+            }) => {
+                return if len == 4 {
+                    write!(f, "{}", f32::from_le_bytes(self.bytes.try_into().unwrap()))
+                } else {
+                    write!(f, "{}", f64::from_le_bytes(self.bytes.try_into().unwrap()))
+                };
+            }
+            Some(ValueType {
+                kind: ValueKind::Signed,
+                ..
+            }) => {
+                value_bytes[..len].copy_from_slice(self.bytes);
+                let shift = 128 - len * 8;
+                let value = i128::from_le_bytes(value_bytes) << shift >> shift;
+                return write!(
+                    f,
+                    "{:#0width$x} ({value})",
+                    u128::from_le_bytes(value_bytes),
+                    width = 2 + len * 2
+                );
+            }
+            Some(_) => {}
+            None if matches!(len, 1 | 2 | 4 | 8) => {}
+            None => {
+                f.write_str("[")?;
+                for (i, byte) in self.bytes.iter().take(MAX_PREVIEW_BYTES).enumerate() {
+                    if i != 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "{byte:02x}")?;
+                }
+                if len > MAX_PREVIEW_BYTES {
+                    f.write_str(" ...")?;
+                }
+                return write!(f, "] ({len} byte{})", if len == 1 { "" } else { "s" });
+            }
+        }
+        value_bytes[..len].copy_from_slice(self.bytes);
+        let value = u128::from_le_bytes(value_bytes);
+        write!(f, "{value:#0width$x} ({value})", width = 2 + len * 2)
+    }
+}
+
+// Walks the structs of a persistent savestate without knowing its schema. As structs aren't marked
+// as such, values are only recognized as structs when they start with a pointer to a valid field
+// table that ends exactly where the value does; anything else (including vectors of structs) is
+// opaque.
+pub struct SaveInspector<'a> {
+    info: SaveInfo,
+    format_version: u16,
+    body: Cow<'a, [u8]>,
+}
+
+impl<'a> SaveInspector<'a> {
+    pub fn new(save: &'a [u8]) -> Result<Self, ReadError> {
+        let (header, body) = decode_container(save)?;
+        Ok(SaveInspector {
+            info: header.info,
+            format_version: header.format_version,
+            body,
+        })
+    }
+
+    #[inline]
+    pub fn info(&self) -> &SaveInfo {
+        &self.info
+    }
+
+    #[inline]
+    pub fn format_version(&self) -> u16 {
+        self.format_version
+    }
+
+    /// Returns the decompressed savestate body.
+    #[inline]
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    #[inline]
+    pub fn bytes(&self, node: &SaveNode) -> &[u8] {
+        &self.body[node.offset..node.offset + node.len]
+    }
+
+    // Splits off the type tag of values that consist of a single raw value, or of a raw slice; the
+    // latter are shown as untyped values.
+    pub fn value(&self, node: &SaveNode) -> SaveValue<'_> {
+        let bytes = self.bytes(node);
+        if self.format_version != 1 && node.fields.is_none() {
+            if let Some((&tag, value_bytes)) = bytes.split_first() {
+                if let Some(ty) = ValueType::from_tag(tag).filter(|ty| ty.size == value_bytes.len())
+                {
+                    return SaveValue {
+                        ty: Some(ty),
+                        bytes: value_bytes,
+                    };
+                }
+                if ValueType::from_slice_tag(tag).is_some_and(|ty| value_bytes.len() % ty.size == 0)
+                {
+                    return SaveValue {
+                        ty: None,
+                        bytes: value_bytes,
+                    };
+                }
+            }
+        }
+        SaveValue { ty: None, bytes }
+    }
+
+    pub fn root(&self) -> SaveNode {
+        self.node(0, self.body.len(), 0)
+    }
+
+    fn node(&self, start: usize, end: usize, depth: usize) -> SaveNode {
+        SaveNode {
+            offset: start,
+            len: end - start,
+            fields: if depth < MAX_DEPTH {
+                self.struct_fields(start, end, depth)
+            } else {
+                None
+            },
+        }
+    }
+
+    fn struct_fields(&self, start: usize, end: usize, depth: usize) -> Option<Vec<SaveField>> {
+        let mut idents = Vec::new();
+        let (struct_info, table_pos) = parse_struct(
+            &mut &*self.body,
+            self.format_version,
+            start as u64,
+            &mut idents,
+        )
+        .ok()?;
+        let table_pos = usize::try_from(table_pos).ok()?;
+        let values_start = start + if self.format_version == 1 { 4 } else { 8 };
+        if struct_info.end != end as u64 || table_pos < values_start || table_pos > end {
+            return None;
+        }
+
+        let mut fields = struct_info.fields;
+        if fields
+            .iter()
+            .any(|field| field.pos < values_start as u64 || field.pos > table_pos as u64)
+        {
+            return None;
+        }
+        // Each field's value ends where the next stored one starts, and the last one right before
+        // the field table.
+        fields.sort_by_key(|field| field.pos);
+
+        Some(
+            fields
+                .iter()
+                .enumerate()
+                .map(|(i, field)| {
+                    let value_end = fields
+                        .get(i + 1)
+                        .map_or(table_pos, |next| next.pos as usize);
+                    SaveField {
+                        name: String::from_utf8_lossy(&idents[field.ident_start..field.ident_end])
+                            .into_owned(),
+                        value: self.node(field.pos as usize, value_end, depth + 1),
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{write_persistent, Savestate, WriteOptions, WriteSavestate};
+
+    #[derive(Savestate)]
+    struct Inner {
+        x: u16,
+        y: i8,
+    }
+
+    #[derive(Savestate)]
+    struct Outer {
+        a: u32,
+        inner: Inner,
+        scale: f32,
+    }
+
+    // Returns the paths and displayed values of all non-struct fields under `node`.
+    fn leaves(inspector: &SaveInspector, node: &SaveNode, path: &str, out: &mut Vec<String>) {
+        for field in node.fields.as_deref().unwrap_or_default() {
+            let path = if path.is_empty() {
+                field.name.clone()
+            } else {
+                format!("{path}.{}", field.name)
+            };
+            if field.value.fields.is_some() {
+                leaves(inspector, &field.value, &path, out);
+            } else {
+                out.push(format!("{path} = {}", inspector.value(&field.value)));
+            }
+        }
+    }
+
+    #[test]
+    fn nested_fields() {
+        let mut value = Outer {
+            a: 5,
+            inner: Inner { x: 0x1234, y: -3 },
+            scale: 1.5,
+        };
+        for options in [
+            WriteOptions::default(),
+            WriteOptions {
+                checksum: true,
+                compression: Some(1),
+            },
+        ] {
+            let save = write_persistent(options, |writer| writer.store(&mut value));
+            let inspector = SaveInspector::new(&save).unwrap();
+            let root = inspector.root();
+            assert_eq!(root.offset, 0);
+            assert_eq!(root.len, inspector.body().len());
+
+            let mut out = Vec::new();
+            leaves(&inspector, &root, "", &mut out);
+            assert_eq!(
+                out,
+                [
+                    "a = 0x00000005 (5)",
+                    "inner.x = 0x1234 (4660)",
+                    "inner.y = 0xfd (-3)",
+                    "scale = 1.5",
+                ]
+            );
+
+            let inner = &root.fields.as_ref().unwrap()[1];
+            assert_eq!(inner.name, "inner");
+            let y = &inner.value.fields.as_ref().unwrap()[1].value;
+            assert_eq!(
+                inspector.value(y),
+                SaveValue {
+                    ty: Some(ValueType::of::<i8>()),
+                    bytes: &[0xFD],
+                }
+            );
+        }
+    }
+}
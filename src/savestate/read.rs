@@ -1,18 +1,107 @@
-use crate::{Bytes, MemValue, OwnedBytesCellPtr};
+#[cfg(debug_assertions)]
+use super::validate;
+use super::{
+    checksum::crc32,
+    compress::decompress,
+    header::{Header, FLAG_CHECKSUM, FLAG_COMPRESSED},
+    value_type::fix_slice_endianness,
+    varint::read_varint,
+    SaveInfo, ValueType,
+};
+use crate::{
+    BoxedByteSlice, Bytes, DirtyTracked, MemValue, OwnedByteSliceCellPtr, OwnedBytesCellPtr,
+};
 use core::{
     cell::Cell,
     convert::Infallible,
-    mem::{size_of, MaybeUninit},
+    fmt, iter,
+    mem::{self, size_of, size_of_val, MaybeUninit},
+    ops::Deref,
     ptr,
     simd::{LaneCount, Simd, SimdElement, SupportedLaneCount},
+    slice, str,
 };
+use std::{borrow::Cow, io};
+
+// Lengths from persistent savestates can't be trusted before loading the elements, so vectors only
+// preallocate up to this many of them upfront.
+const MAX_PREALLOCATED_LEN: usize = 0x1000;
 
 pub trait LoadableInPlace {
     fn load_in_place<S: ReadSavestate>(&mut self, save: &mut S) -> Result<(), S::Error>;
+
+    // Loads all elements of `slice` in order; overridden by raw values to load them in bulk.
+    #[inline]
+    fn load_slice_in_place<S: ReadSavestate>(
+        slice: &mut [Self],
+        save: &mut S,
+    ) -> Result<(), S::Error>
+    where
+        Self: Sized,
+    {
+        for elem in slice {
+            elem.load_in_place(save)?;
+        }
+        Ok(())
+    }
+
+    // Replaces the contents of `vec` with `len` elements stored by `Storable::store_slice`,
+    // loading the existing ones in place and reusing its allocation; overridden by raw values to
+    // load them in bulk.
+    #[inline]
+    fn load_vec_in_place<S: ReadSavestate>(
+        vec: &mut Vec<Self>,
+        len: usize,
+        save: &mut S,
+    ) -> Result<(), S::Error>
+    where
+        Self: Loadable,
+    {
+        vec.truncate(len);
+        for elem in vec.iter_mut() {
+            elem.load_in_place(save)?;
+        }
+        let remaining = len - vec.len();
+        vec.reserve(if S::TRANSIENT {
+            remaining
+        } else {
+            remaining.min(MAX_PREALLOCATED_LEN)
+        });
+        for _ in 0..remaining {
+            vec.push(save.load()?);
+        }
+        Ok(())
+    }
 }
 
 pub trait Loadable: Sized {
     fn load<S: ReadSavestate>(save: &mut S) -> Result<Self, S::Error>;
+
+    // The following load sequences of elements stored by `Storable::store_slice`, and are
+    // overridden by raw values to load them in bulk.
+    #[inline]
+    fn load_array<S: ReadSavestate, const LEN: usize>(
+        save: &mut S,
+    ) -> Result<[Self; LEN], S::Error> {
+        let mut result = [const { MaybeUninit::uninit() }; LEN];
+        for elem in &mut result {
+            *elem = MaybeUninit::new(save.load()?);
+        }
+        Ok(unsafe { MaybeUninit::array_assume_init(result) })
+    }
+
+    #[inline]
+    fn load_vec<S: ReadSavestate>(len: usize, save: &mut S) -> Result<Vec<Self>, S::Error> {
+        let mut result = Vec::with_capacity(if S::TRANSIENT {
+            len
+        } else {
+            len.min(MAX_PREALLOCATED_LEN)
+        });
+        for _ in 0..len {
+            result.push(save.load()?);
+        }
+        Ok(result)
+    }
 }
 
 pub trait ReadSavestate: Sized {
@@ -20,14 +109,72 @@ pub trait ReadSavestate: Sized {
 
     const TRANSIENT: bool;
 
+    fn load_array_len(&mut self) -> Result<usize, Self::Error>;
     fn load_raw<T: MemValue>(&mut self) -> Result<T, Self::Error>;
-    fn load_bytes(&mut self, len: usize) -> Result<*const u8, Self::Error>;
-
-    fn invalid_enum() -> Self::Error;
+    // Load pointer-sized integers, which persistent savestates store as 64-bit ones; values that
+    // don't fit in the target's are rejected.
+    fn load_usize(&mut self) -> Result<usize, Self::Error>;
+    fn load_isize(&mut self) -> Result<isize, Self::Error>;
+    // Loads `values.len()` raw values stored by `WriteSavestate::store_raw_slice`.
+    fn load_raw_slice<T: MemValue>(&mut self, values: &mut [T]) -> Result<(), Self::Error>;
+    // Replaces the contents of `values` with `len` raw values stored by
+    // `WriteSavestate::store_raw_slice`, reusing its allocation. Readers of untrusted savestates
+    // should check that `len` values are present before allocating space for them.
+    #[inline]
+    fn load_raw_vec<T: MemValue>(
+        &mut self,
+        len: usize,
+        values: &mut Vec<T>,
+    ) -> Result<(), Self::Error> {
+        values.clear();
+        values.resize(len, unsafe { mem::zeroed() });
+        self.load_raw_slice(values)
+    }
+    fn load_bytes(&mut self, bytes: &mut [u8]) -> Result<(), Self::Error>;
+    // Checks that `len` bytes can be loaded by `load_bytes` before space is allocated for them, as
+    // lengths loaded from untrusted savestates may be corrupted.
+    fn check_bytes_len(&mut self, len: usize) -> Result<(), Self::Error>;
+
+    fn invalid_enum(&self) -> Self::Error;
+    fn invalid_utf8(&self) -> Self::Error;
+    // Returned when a loaded length doesn't match the one expected, or exceeds it for values with a
+    // maximum capacity.
+    fn invalid_length(&self, expected: u64, found: u64) -> Self::Error;
+    fn field_not_found(&self, ident: &[u8]) -> Self::Error;
+    fn unsupported_struct_version(&self, version: u32) -> Self::Error;
 
     fn start_struct(&mut self) -> Result<(), Self::Error>;
     fn end_struct(&mut self) -> Result<(), Self::Error>;
-    fn start_field(&mut self, ident: &[u8]) -> Result<(), Self::Error>;
+    // Returns the layout version recorded for the current struct, or 0 if there was none; transient
+    // savestates don't record versions, as they're always loaded using the current layout.
+    fn struct_version(&mut self) -> Result<u32, Self::Error>;
+    // Whether enums were stored as the positional index of their variant, as in version 1 of the
+    // persistent format, rather than as their tag.
+    #[inline]
+    fn legacy_enum_indices(&self) -> bool {
+        false
+    }
+    // Looks up the given field, falling back to each of `aliases` in order if it's not present;
+    // returns whether any of them was found.
+    fn try_start_field_aliased(
+        &mut self,
+        ident: &[u8],
+        aliases: &[&[u8]],
+    ) -> Result<bool, Self::Error>;
+
+    #[inline]
+    fn start_field_aliased(&mut self, ident: &[u8], aliases: &[&[u8]]) -> Result<(), Self::Error> {
+        if self.try_start_field_aliased(ident, aliases)? {
+            Ok(())
+        } else {
+            Err(self.field_not_found(ident))
+        }
+    }
+
+    #[inline]
+    fn start_field(&mut self, ident: &[u8]) -> Result<(), Self::Error> {
+        self.start_field_aliased(ident, &[])
+    }
 
     #[inline]
     fn load<T: Loadable>(&mut self) -> Result<T, Self::Error> {
@@ -38,12 +185,20 @@ pub trait ReadSavestate: Sized {
     fn load_into<T: LoadableInPlace>(&mut self, value: &mut T) -> Result<(), Self::Error> {
         value.load_in_place(self)
     }
+
+    // Loads the elements of `slice` in place, without its length.
+    #[inline]
+    fn load_slice<T: LoadableInPlace>(&mut self, slice: &mut [T]) -> Result<(), Self::Error> {
+        T::load_slice_in_place(slice, self)
+    }
 }
 
 // Used for fast, unchecked in-memory savestates (i.e. rewinding).
 pub struct TransientReadSavestate<'a> {
     save: &'a [u8],
     pos: u32,
+    #[cfg(debug_assertions)]
+    path: validate::FieldPath,
 }
 
 impl<'a> TransientReadSavestate<'a> {
@@ -51,7 +206,46 @@ impl<'a> TransientReadSavestate<'a> {
     /// The given save's length must be less than `0x1_0000_0000` bytes, and all subsequent reads
     /// must not go out of bounds.
     pub unsafe fn new(save: &'a [u8]) -> Self {
-        TransientReadSavestate { save, pos: 0 }
+        TransientReadSavestate {
+            save,
+            pos: 0,
+            #[cfg(debug_assertions)]
+            path: validate::FieldPath::default(),
+        }
+    }
+
+    #[inline]
+    fn read<T: MemValue>(&mut self) -> T {
+        let start = self.pos as usize;
+        #[cfg(debug_assertions)]
+        self.check_bounds(size_of::<T>());
+        self.pos = (start + size_of::<T>()) as u32;
+        unsafe { T::read_ne(self.save.as_ptr().add(start) as *const T) }
+    }
+
+    #[cfg(debug_assertions)]
+    fn check_bounds(&self, len: usize) {
+        let remaining = self.save.len() - self.pos as usize;
+        if len > remaining {
+            self.path.mismatch(
+                self.pos as usize,
+                &format!("{len} more bytes"),
+                &format!("{remaining} bytes left"),
+            );
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    fn expect_marker(&mut self, expected: u8) {
+        let pos = self.pos as usize;
+        let found = self.read::<u8>();
+        if found != expected {
+            self.path.mismatch(
+                pos,
+                &validate::describe_marker(expected),
+                &validate::describe_marker(found),
+            );
+        }
     }
 }
 
@@ -60,189 +254,971 @@ impl<'a> ReadSavestate for TransientReadSavestate<'a> {
 
     const TRANSIENT: bool = true;
 
-    fn invalid_enum() -> Self::Error {
+    fn invalid_enum(&self) -> Self::Error {
+        unreachable!();
+    }
+
+    fn invalid_utf8(&self) -> Self::Error {
+        unreachable!();
+    }
+
+    fn invalid_length(&self, _expected: u64, _found: u64) -> Self::Error {
+        unreachable!();
+    }
+
+    fn field_not_found(&self, _ident: &[u8]) -> Self::Error {
         unreachable!();
     }
 
+    fn unsupported_struct_version(&self, _version: u32) -> Self::Error {
+        unreachable!();
+    }
+
+    #[inline]
+    fn load_array_len(&mut self) -> Result<usize, Self::Error> {
+        #[cfg(debug_assertions)]
+        self.expect_marker(validate::ARRAY_LEN);
+        Ok(self.read::<u32>() as usize)
+    }
+
     #[inline]
     fn load_raw<T: MemValue>(&mut self) -> Result<T, Self::Error> {
+        #[cfg(debug_assertions)]
+        self.expect_marker(ValueType::of::<T>().to_tag());
+        Ok(self.read())
+    }
+
+    #[inline]
+    fn load_usize(&mut self) -> Result<usize, Self::Error> {
+        self.load_raw()
+    }
+
+    #[inline]
+    fn load_isize(&mut self) -> Result<isize, Self::Error> {
+        self.load_raw()
+    }
+
+    #[inline]
+    fn load_raw_slice<T: MemValue>(&mut self, values: &mut [T]) -> Result<(), Self::Error> {
+        #[cfg(debug_assertions)]
+        {
+            let pos = self.pos as usize;
+            self.expect_marker(validate::SLICE);
+            self.expect_marker(ValueType::of::<T>().to_tag());
+            let len = self.read::<u32>() as usize;
+            if len != values.len() {
+                self.path.mismatch(
+                    pos,
+                    &format!("{} values", values.len()),
+                    &format!("{len} values"),
+                );
+            }
+            self.check_bounds(size_of_val(values));
+        }
         let start = self.pos as usize;
-        self.pos = (start + size_of::<T>()) as u32;
-        Ok(unsafe { T::read_ne(self.save.as_ptr().add(start) as *const T) })
+        self.pos = (start + size_of_val(values)) as u32;
+        unsafe {
+            ptr::copy_nonoverlapping(
+                self.save.as_ptr().add(start),
+                values.as_mut_ptr() as *mut u8,
+                size_of_val(values),
+            );
+        }
+        Ok(())
     }
 
     #[inline]
-    fn load_bytes(&mut self, len: usize) -> Result<*const u8, Self::Error> {
+    fn load_bytes(&mut self, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        #[cfg(debug_assertions)]
+        {
+            let pos = self.pos as usize;
+            self.expect_marker(validate::BYTES);
+            let len = self.read::<u32>() as usize;
+            if len != bytes.len() {
+                self.path.mismatch(
+                    pos,
+                    &format!("{} bytes", bytes.len()),
+                    &format!("{len} bytes"),
+                );
+            }
+            self.check_bounds(len);
+        }
         let start = self.pos as usize;
-        self.pos = (start + len) as u32;
-        Ok(unsafe { self.save.as_ptr().add(start) })
+        self.pos = (start + bytes.len()) as u32;
+        unsafe {
+            ptr::copy_nonoverlapping(
+                self.save.as_ptr().add(start),
+                bytes.as_mut_ptr(),
+                bytes.len(),
+            );
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn check_bytes_len(&mut self, _len: usize) -> Result<(), Self::Error> {
+        Ok(())
     }
 
     #[inline]
     fn start_struct(&mut self) -> Result<(), Self::Error> {
+        #[cfg(debug_assertions)]
+        {
+            self.expect_marker(validate::STRUCT_START);
+            self.path.start_struct();
+        }
         Ok(())
     }
 
     #[inline]
     fn end_struct(&mut self) -> Result<(), Self::Error> {
+        #[cfg(debug_assertions)]
+        {
+            self.expect_marker(validate::STRUCT_END);
+            self.path.end_struct();
+        }
         Ok(())
     }
 
     #[inline]
-    fn start_field(&mut self, _ident: &[u8]) -> Result<(), Self::Error> {
-        Ok(())
+    fn struct_version(&mut self) -> Result<u32, Self::Error> {
+        unreachable!();
+    }
+
+    #[inline]
+    fn try_start_field_aliased(
+        &mut self,
+        _ident: &[u8],
+        _aliases: &[&[u8]],
+    ) -> Result<bool, Self::Error> {
+        #[cfg(debug_assertions)]
+        {
+            self.path.start_field(_ident);
+            let pos = self.pos as usize;
+            self.expect_marker(validate::FIELD);
+            if self.read::<u32>() != validate::ident_hash(_ident) {
+                self.path
+                    .mismatch(pos, "this field", "a field with a different identifier");
+            }
+        }
+        Ok(true)
     }
 }
 
-struct StructInfo<'a> {
-    fields: Vec<(&'a [u8], u32)>,
-    end: u32,
-    cur_field: u8, // Used to speed up lookup, assuming a linear field order
+#[derive(Clone, Copy)]
+pub(super) struct FieldInfo {
+    pub ident_start: usize,
+    pub ident_end: usize,
+    pub pos: u64,
 }
 
-// Used for checked savestates that will be saved to disk, and need compatibility across field order
-// changes, additions and deletions.
-pub struct PersistentReadSavestate<'a> {
-    save: &'a [u8],
-    pos: u32,
-    structs: Vec<StructInfo<'a>>,
+#[derive(Clone)]
+pub(super) struct StructInfo {
+    pub version: u32,
+    pub fields: Vec<FieldInfo>,
+    pub end: u64,
+    pub cur_field: usize, // Used to speed up lookup, assuming a linear field order
+    pub active_field: Option<usize>,
 }
 
-impl<'a> PersistentReadSavestate<'a> {
-    pub fn new(save: &'a [u8]) -> Result<Self, ()> {
-        if save.len() > u32::MAX as usize {
-            return Err(());
+impl StructInfo {
+    // Returns the position of the given field's value; identifier ranges index into `idents`.
+    pub(super) fn find_field(&mut self, idents: &[u8], ident: &[u8]) -> Option<u64> {
+        self.active_field = None;
+        let len = self.fields.len();
+        if len == 0 {
+            return None;
         }
-        Ok(PersistentReadSavestate {
-            save,
+        let mut i = self.cur_field;
+        loop {
+            let field_index = i;
+            let field = self.fields[i];
+            i += 1;
+            if i == len {
+                i = 0;
+            }
+            if &idents[field.ident_start..field.ident_end] == ident {
+                self.cur_field = i;
+                self.active_field = Some(field_index);
+                return Some(field.pos);
+            }
+            if i == self.cur_field {
+                return None;
+            }
+        }
+    }
+
+    pub(super) fn find_field_aliased(
+        &mut self,
+        idents: &[u8],
+        ident: &[u8],
+        aliases: &[&[u8]],
+    ) -> Option<u64> {
+        iter::once(ident)
+            .chain(aliases.iter().copied())
+            .find_map(|ident| self.find_field(idents, ident))
+    }
+}
+
+// Returns the dot-separated identifiers of the fields currently being loaded, for error reporting.
+pub(super) fn field_path<'a>(
+    structs: impl IntoIterator<Item = &'a StructInfo>,
+    idents: &[u8],
+) -> String {
+    let mut path = String::new();
+    for struct_info in structs {
+        if let Some(field) = struct_info.active_field.map(|i| struct_info.fields[i]) {
+            if !path.is_empty() {
+                path.push('.');
+            }
+            path.push_str(&String::from_utf8_lossy(
+                &idents[field.ident_start..field.ident_end],
+            ));
+        }
+    }
+    path
+}
+
+// Validates the header and checksum of a persistent savestate, and returns its decompressed body.
+pub(super) fn decode_container(save: &[u8]) -> Result<(Header, Cow<'_, [u8]>), ReadError> {
+    let (header, header_len) = Header::parse(save)?;
+
+    let save = if header.flags & FLAG_CHECKSUM != 0 {
+        let checksum_start = save
+            .len()
+            .checked_sub(4)
+            .filter(|start| *start >= header_len)
+            .ok_or(ReadErrorKind::UnexpectedEof {
+                expected: 4,
+                found: (save.len() - header_len) as u64,
+            })?;
+        let (contents, checksum) = save.split_at(checksum_start);
+        if crc32(contents) != u32::from_le_bytes(checksum.try_into().unwrap()) {
+            return Err(ReadErrorKind::ChecksumMismatch.into());
+        }
+        contents
+    } else {
+        save
+    };
+
+    // Offsets are relative to the start of the body, right after the header.
+    let body = &save[header_len..];
+    let body = if header.flags & FLAG_COMPRESSED != 0 {
+        let (len, data) = body
+            .split_first_chunk::<8>()
+            .ok_or(ReadErrorKind::UnexpectedEof {
+                expected: 8,
+                found: body.len() as u64,
+            })?;
+        let len =
+            usize::try_from(u64::from_le_bytes(*len)).map_err(|_| ReadErrorKind::SaveTooLarge)?;
+        Cow::Owned(decompress(data, len).ok_or(ReadErrorKind::InvalidCompressedData)?)
+    } else {
+        Cow::Borrowed(body)
+    };
+
+    Ok((header, body))
+}
+
+// Random-access contents of a persistent savestate body, decoded by `PersistentDecoder`.
+pub(super) trait BodySource {
+    fn body_len(&self) -> u64;
+
+    // Fills `bytes` with the contents of the body starting at `pos`, which are known to be in
+    // bounds.
+    fn read_in_bounds(&mut self, pos: u64, bytes: &mut [u8]) -> Result<(), ReadError>;
+
+    // Returns an error if fewer than `len` bytes are left in the body after `pos`.
+    #[inline]
+    fn check_len(&self, pos: u64, len: u64) -> Result<(), ReadError> {
+        let found = self.body_len().saturating_sub(pos);
+        if len > found {
+            return Err(ReadErrorKind::UnexpectedEof {
+                expected: len,
+                found,
+            }
+            .at(pos));
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn read_at(&mut self, pos: u64, bytes: &mut [u8]) -> Result<(), ReadError> {
+        self.check_len(pos, bytes.len() as u64)?;
+        self.read_in_bounds(pos, bytes)
+    }
+
+    #[inline]
+    fn read_u8_at(&mut self, pos: &mut u64) -> Result<u8, ReadError> {
+        let mut byte = [0];
+        self.read_at(*pos, &mut byte)?;
+        *pos += 1;
+        Ok(byte[0])
+    }
+
+    #[inline]
+    fn read_varint_at(&mut self, pos: &mut u64) -> Result<u64, ReadError> {
+        let start = *pos;
+        read_varint(|| self.read_u8_at(pos))?.ok_or(ReadErrorKind::InvalidVarint.at(start))
+    }
+}
+
+impl<T: Deref<Target = [u8]>> BodySource for T {
+    #[inline]
+    fn body_len(&self) -> u64 {
+        self.deref().len() as u64
+    }
+
+    #[inline]
+    fn read_in_bounds(&mut self, pos: u64, bytes: &mut [u8]) -> Result<(), ReadError> {
+        let pos = pos as usize;
+        bytes.copy_from_slice(&self[pos..pos + bytes.len()]);
+        Ok(())
+    }
+}
+
+// Parses the field table of the struct starting at `struct_pos`, returning it along with the
+// table's position; identifiers are appended to `idents`, which their ranges index into.
+pub(super) fn parse_struct<B: BodySource>(
+    body: &mut B,
+    format_version: u16,
+    struct_pos: u64,
+    idents: &mut Vec<u8>,
+) -> Result<(StructInfo, u64), ReadError> {
+    let (table_pos, mut pos, version, fields_len) = if format_version == 1 {
+        let mut table_pos = [0; 4];
+        body.read_at(struct_pos, &mut table_pos)?;
+        let table_pos = u32::from_le_bytes(table_pos) as u64;
+        let mut pos = table_pos;
+        let fields_len = body.read_u8_at(&mut pos)? as u64;
+        (table_pos, pos, 0, fields_len)
+    } else {
+        let mut table_pos = [0; 8];
+        body.read_at(struct_pos, &mut table_pos)?;
+        let table_pos = u64::from_le_bytes(table_pos);
+        let mut pos = table_pos;
+        let version = u32::try_from(body.read_varint_at(&mut pos)?)
+            .map_err(|_| ReadErrorKind::InvalidVarint.at(table_pos))?;
+        let fields_len = body.read_varint_at(&mut pos)?;
+        (table_pos, pos, version, fields_len)
+    };
+
+    // Every field takes up at least two bytes, which bounds the allocation for corrupted saves
+    let max_fields_len = body.body_len().saturating_sub(pos) / 2;
+    let mut fields = Vec::with_capacity(fields_len.min(max_fields_len) as usize);
+    for _ in 0..fields_len {
+        let ident_start = idents.len();
+        loop {
+            let byte = body.read_u8_at(&mut pos)?;
+            if byte == 0 {
+                break;
+            }
+            idents.push(byte);
+        }
+        let ident_end = idents.len();
+
+        let value_pos = if format_version == 1 {
+            let mut value_pos = [0; 4];
+            body.read_at(pos, &mut value_pos)?;
+            pos += 4;
+            u32::from_le_bytes(value_pos) as u64
+        } else {
+            struct_pos.wrapping_add(body.read_varint_at(&mut pos)?)
+        };
+
+        fields.push(FieldInfo {
+            ident_start,
+            ident_end,
+            pos: value_pos,
+        });
+    }
+
+    Ok((
+        StructInfo {
+            version,
+            fields,
+            end: pos,
+            cur_field: 0,
+            active_field: None,
+        },
+        table_pos,
+    ))
+}
+
+// Loads values from the body of a persistent savestate; shared by the readers of persistent
+// savestates held in memory and read from streams.
+pub(super) struct PersistentDecoder<B: BodySource> {
+    pub body: B,
+    format_version: u16,
+    pos: u64,
+    // Identifiers of the fields of all structs in `structs`, each one owning the part of the buffer
+    // after the given start index.
+    idents: Vec<u8>,
+    structs: Vec<(StructInfo, usize)>,
+}
+
+impl<B: BodySource> PersistentDecoder<B> {
+    pub fn new(body: B, format_version: u16) -> Self {
+        PersistentDecoder {
+            body,
+            format_version,
             pos: 0,
+            idents: Vec::new(),
             structs: Vec::new(),
-        })
+        }
+    }
+
+    // Adds the path of the fields being loaded to errors, along with the current position if they
+    // don't have a more precise one.
+    fn add_context(&self, mut err: ReadError) -> ReadError {
+        err.path = field_path(self.structs.iter().map(|(info, _)| info), &self.idents);
+        err.offset.get_or_insert(self.pos);
+        err
+    }
+
+    // Reads the type from the tag of a raw slice, checking that its values can be loaded as `T`s.
+    fn slice_type<T: MemValue>(&mut self) -> Result<ValueType, ReadError> {
+        let mut pos = self.pos;
+        let tag = self
+            .body
+            .read_u8_at(&mut pos)
+            .map_err(|err| self.add_context(err))?;
+        let ty = ValueType::from_slice_tag(tag)
+            .ok_or_else(|| self.add_context(ReadErrorKind::InvalidTypeTag.into()))?;
+        let expected = ValueType::of::<T>();
+        if ty != expected && !ty.widens_to(expected) {
+            return Err(self.add_context(
+                ReadErrorKind::TypeMismatch {
+                    expected,
+                    found: ty,
+                }
+                .into(),
+            ));
+        }
+        Ok(ty)
+    }
+
+    fn load_slice_values<T: MemValue>(
+        &mut self,
+        ty: ValueType,
+        values: &mut [T],
+    ) -> Result<(), ReadError> {
+        let pos = self.pos + 1;
+        let len = values.len() * ty.size;
+        if ty == ValueType::of::<T>() {
+            let bytes = unsafe { slice::from_raw_parts_mut(values.as_mut_ptr() as *mut u8, len) };
+            self.body
+                .read_at(pos, bytes)
+                .map_err(|err| self.add_context(err))?;
+            unsafe { fix_slice_endianness(values.as_mut_ptr(), values.len()) };
+        } else {
+            let mut bytes = vec![0; len];
+            self.body
+                .read_at(pos, &mut bytes)
+                .map_err(|err| self.add_context(err))?;
+            // `slice_type` already checked that the values can be widened
+            for (value, bytes) in values.iter_mut().zip(bytes.chunks_exact(ty.size)) {
+                *value = ty.read_as(bytes).unwrap();
+            }
+        }
+        self.pos = pos + len as u64;
+        Ok(())
     }
 }
 
-#[derive(Clone, Copy, Debug)]
-pub enum ReadError {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadErrorKind {
     FieldNotFound,
-    UnexpectedEof,
+    UnexpectedEof {
+        /// Number of bytes that needed to be read.
+        expected: u64,
+        /// Number of bytes that were actually available.
+        found: u64,
+    },
     NoStructPresent,
     InvalidEnum,
+    InvalidUtf8,
+    ValueOutOfRange,
+    UnsupportedStructVersion(u32),
+    InvalidVarint,
+    InvalidTypeTag,
+    TypeMismatch {
+        expected: ValueType,
+        found: ValueType,
+    },
+    InvalidMagic,
+    UnsupportedVersion(u16),
+    InvalidHeader,
+    SaveTooLarge,
+    ChecksumMismatch,
+    InvalidCompressedData,
+    UnsupportedCompression,
+    InvalidSyntax {
+        line: usize,
+        column: usize,
+    },
+    UnexpectedValue,
+    InvalidDelta,
+    LengthMismatch {
+        expected: u64,
+        found: u64,
+    },
+    Io(io::ErrorKind),
+}
+
+impl ReadErrorKind {
+    #[inline]
+    pub(super) fn at(self, offset: u64) -> ReadError {
+        ReadError {
+            kind: self,
+            path: String::new(),
+            offset: Some(offset),
+        }
+    }
 }
 
-impl<'a> ReadSavestate for PersistentReadSavestate<'a> {
+impl fmt::Display for ReadErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadErrorKind::FieldNotFound => f.write_str("field not found"),
+            ReadErrorKind::UnexpectedEof { expected, found } => write!(
+                f,
+                "unexpected end of savestate (expected {expected} bytes, found {found})"
+            ),
+            ReadErrorKind::NoStructPresent => f.write_str("no struct is being loaded"),
+            ReadErrorKind::InvalidEnum => f.write_str("invalid enum value"),
+            ReadErrorKind::InvalidUtf8 => f.write_str("invalid UTF-8 string"),
+            ReadErrorKind::ValueOutOfRange => f.write_str("value out of range for this target"),
+            ReadErrorKind::UnsupportedStructVersion(version) => {
+                write!(f, "unsupported struct version {version}")
+            }
+            ReadErrorKind::InvalidVarint => f.write_str("invalid variable-length integer"),
+            ReadErrorKind::InvalidTypeTag => f.write_str("invalid value type tag"),
+            ReadErrorKind::TypeMismatch { expected, found } => {
+                write!(f, "type mismatch (expected {expected}, found {found})")
+            }
+            ReadErrorKind::InvalidMagic => f.write_str("not a savestate"),
+            ReadErrorKind::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {version}")
+            }
+            ReadErrorKind::InvalidHeader => f.write_str("invalid header"),
+            ReadErrorKind::SaveTooLarge => f.write_str("savestate too large"),
+            ReadErrorKind::ChecksumMismatch => f.write_str("checksum mismatch"),
+            ReadErrorKind::InvalidCompressedData => f.write_str("invalid compressed data"),
+            ReadErrorKind::UnsupportedCompression => {
+                f.write_str("compressed savestates can't be streamed")
+            }
+            ReadErrorKind::InvalidSyntax { line, column } => {
+                write!(f, "syntax error at line {line}, column {column}")
+            }
+            ReadErrorKind::UnexpectedValue => f.write_str("unexpected or missing value"),
+            ReadErrorKind::InvalidDelta => f.write_str("invalid delta"),
+            ReadErrorKind::LengthMismatch { expected, found } => {
+                write!(f, "length mismatch (expected {expected}, found {found})")
+            }
+            ReadErrorKind::Io(kind) => write!(f, "I/O error: {kind}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ReadError {
+    pub kind: ReadErrorKind,
+    /// Dot-separated names of the fields being loaded when the error occurred, i.e.
+    /// `gpu.engine_3d.vertex_ram`; empty outside of struct fields.
+    pub path: String,
+    /// Position inside the savestate body at which the error occurred, if any.
+    pub offset: Option<u64>,
+}
+
+impl From<ReadErrorKind> for ReadError {
+    #[inline]
+    fn from(kind: ReadErrorKind) -> Self {
+        ReadError {
+            kind,
+            path: String::new(),
+            offset: None,
+        }
+    }
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        match (self.path.is_empty(), self.offset) {
+            (true, None) => Ok(()),
+            (true, Some(offset)) => write!(f, " (offset {offset:#x})"),
+            (false, None) => write!(f, " (field `{}`)", self.path),
+            (false, Some(offset)) => write!(f, " (field `{}`, offset {offset:#x})", self.path),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {}
+
+impl<B: BodySource> ReadSavestate for PersistentDecoder<B> {
     type Error = ReadError;
 
     const TRANSIENT: bool = false;
 
-    fn invalid_enum() -> Self::Error {
-        ReadError::InvalidEnum
+    fn invalid_enum(&self) -> Self::Error {
+        self.add_context(ReadErrorKind::InvalidEnum.into())
+    }
+
+    fn invalid_utf8(&self) -> Self::Error {
+        self.add_context(ReadErrorKind::InvalidUtf8.into())
+    }
+
+    fn invalid_length(&self, expected: u64, found: u64) -> Self::Error {
+        self.add_context(ReadErrorKind::LengthMismatch { expected, found }.into())
+    }
+
+    fn field_not_found(&self, ident: &[u8]) -> Self::Error {
+        let mut err = self.add_context(ReadErrorKind::FieldNotFound.into());
+        if !err.path.is_empty() {
+            err.path.push('.');
+        }
+        err.path.push_str(&String::from_utf8_lossy(ident));
+        err
+    }
+
+    fn unsupported_struct_version(&self, version: u32) -> Self::Error {
+        self.add_context(ReadErrorKind::UnsupportedStructVersion(version).into())
+    }
+
+    #[inline]
+    fn load_array_len(&mut self) -> Result<usize, Self::Error> {
+        if self.format_version == 1 {
+            return self.load_raw::<u32>().map(|len| len as usize);
+        }
+        let mut pos = self.pos;
+        let len = self
+            .body
+            .read_varint_at(&mut pos)
+            .map_err(|err| self.add_context(err))?;
+        self.pos = pos;
+        usize::try_from(len).map_err(|_| self.add_context(ReadErrorKind::SaveTooLarge.into()))
+    }
+
+    #[inline]
+    fn load_raw<T: MemValue>(&mut self) -> Result<T, Self::Error> {
+        if self.format_version == 1 {
+            let mut value = MaybeUninit::<T>::zeroed();
+            let bytes =
+                unsafe { slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, size_of::<T>()) };
+            self.body
+                .read_at(self.pos, bytes)
+                .map_err(|err| self.add_context(err))?;
+            self.pos += size_of::<T>() as u64;
+            return Ok(unsafe { T::read_le(value.as_ptr()) });
+        }
+
+        let mut pos = self.pos;
+        let tag = self
+            .body
+            .read_u8_at(&mut pos)
+            .map_err(|err| self.add_context(err))?;
+        let ty = ValueType::from_tag(tag)
+            .ok_or_else(|| self.add_context(ReadErrorKind::InvalidTypeTag.into()))?;
+        let mut bytes = [0; 16];
+        let bytes = &mut bytes[..ty.size];
+        self.body
+            .read_at(pos, bytes)
+            .map_err(|err| self.add_context(err))?;
+        let value = ty.read_as(bytes).ok_or_else(|| {
+            self.add_context(
+                ReadErrorKind::TypeMismatch {
+                    expected: ValueType::of::<T>(),
+                    found: ty,
+                }
+                .into(),
+            )
+        })?;
+        self.pos = pos + ty.size as u64;
+        Ok(value)
+    }
+
+    #[inline]
+    fn load_usize(&mut self) -> Result<usize, Self::Error> {
+        // Version 1 stores pointer-sized integers as 32-bit ones, without a type tag to widen them
+        // from
+        if self.format_version == 1 {
+            return self.load_raw::<u32>().map(|value| value as usize);
+        }
+        let pos = self.pos;
+        usize::try_from(self.load_raw::<u64>()?)
+            .map_err(|_| self.add_context(ReadErrorKind::ValueOutOfRange.at(pos)))
+    }
+
+    #[inline]
+    fn load_isize(&mut self) -> Result<isize, Self::Error> {
+        if self.format_version == 1 {
+            return self.load_raw::<i32>().map(|value| value as isize);
+        }
+        let pos = self.pos;
+        isize::try_from(self.load_raw::<i64>()?)
+            .map_err(|_| self.add_context(ReadErrorKind::ValueOutOfRange.at(pos)))
+    }
+
+    #[inline]
+    fn load_raw_slice<T: MemValue>(&mut self, values: &mut [T]) -> Result<(), Self::Error> {
+        if self.format_version == 1 {
+            for value in values {
+                *value = self.load_raw()?;
+            }
+            return Ok(());
+        }
+        let ty = self.slice_type::<T>()?;
+        self.load_slice_values(ty, values)
+    }
+
+    fn load_raw_vec<T: MemValue>(
+        &mut self,
+        len: usize,
+        values: &mut Vec<T>,
+    ) -> Result<(), Self::Error> {
+        values.clear();
+        if self.format_version == 1 {
+            for _ in 0..len {
+                values.push(self.load_raw()?);
+            }
+            return Ok(());
+        }
+        let ty = self.slice_type::<T>()?;
+        self.body
+            .check_len(self.pos + 1, (len as u64).saturating_mul(ty.size as u64))
+            .map_err(|err| self.add_context(err))?;
+        values.resize(len, unsafe { mem::zeroed() });
+        self.load_slice_values(ty, values)
+    }
+
+    #[inline]
+    fn load_bytes(&mut self, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.body
+            .read_at(self.pos, bytes)
+            .map_err(|err| self.add_context(err))?;
+        self.pos += bytes.len() as u64;
+        Ok(())
+    }
+
+    #[inline]
+    fn check_bytes_len(&mut self, len: usize) -> Result<(), Self::Error> {
+        self.body
+            .check_len(self.pos, len as u64)
+            .map_err(|err| self.add_context(err))
+    }
+
+    #[inline]
+    fn start_struct(&mut self) -> Result<(), Self::Error> {
+        let idents_start = self.idents.len();
+        let (struct_info, _) = parse_struct(
+            &mut self.body,
+            self.format_version,
+            self.pos,
+            &mut self.idents,
+        )
+        .map_err(|err| {
+            self.idents.truncate(idents_start);
+            self.add_context(err)
+        })?;
+        self.structs.push((struct_info, idents_start));
+        Ok(())
+    }
+
+    #[inline]
+    fn end_struct(&mut self) -> Result<(), Self::Error> {
+        match self.structs.pop() {
+            Some((struct_info, idents_start)) => {
+                self.idents.truncate(idents_start);
+                self.pos = struct_info.end;
+                Ok(())
+            }
+            None => Err(self.add_context(ReadErrorKind::NoStructPresent.into())),
+        }
+    }
+
+    #[inline]
+    fn struct_version(&mut self) -> Result<u32, Self::Error> {
+        self.structs
+            .last()
+            .map(|(struct_info, _)| struct_info.version)
+            .ok_or_else(|| self.add_context(ReadErrorKind::NoStructPresent.into()))
+    }
+
+    #[inline]
+    fn legacy_enum_indices(&self) -> bool {
+        self.format_version == 1
     }
 
-    #[inline]
-    fn load_raw<T: MemValue>(&mut self) -> Result<T, Self::Error> {
-        let start = self.pos as usize;
-        let end = start + size_of::<T>();
-        if end > self.save.len() {
-            return Err(ReadError::UnexpectedEof);
-        }
-        self.pos = end as u32;
-        Ok(unsafe { T::read_le(self.save.as_ptr().add(start) as *const T) })
-    }
+    #[inline]
+    fn try_start_field_aliased(
+        &mut self,
+        ident: &[u8],
+        aliases: &[&[u8]],
+    ) -> Result<bool, Self::Error> {
+        let Some((cur_struct, _)) = self.structs.last_mut() else {
+            return Err(self.add_context(ReadErrorKind::NoStructPresent.into()));
+        };
+        let Some(pos) = cur_struct.find_field_aliased(&self.idents, ident, aliases) else {
+            return Ok(false);
+        };
+        self.pos = pos;
+        Ok(true)
+    }
+}
+
+// Implements `ReadSavestate` for a reader by forwarding to its `decoder` field.
+macro_rules! forward_read_savestate {
+    ([$($generics: tt)*] $ty: ty) => {
+        impl<$($generics)*> ReadSavestate for $ty {
+            type Error = ReadError;
+
+            const TRANSIENT: bool = false;
+
+            fn invalid_enum(&self) -> Self::Error {
+                self.decoder.invalid_enum()
+            }
+
+            fn invalid_utf8(&self) -> Self::Error {
+                self.decoder.invalid_utf8()
+            }
+
+            fn invalid_length(&self, expected: u64, found: u64) -> Self::Error {
+                self.decoder.invalid_length(expected, found)
+            }
+
+            fn field_not_found(&self, ident: &[u8]) -> Self::Error {
+                self.decoder.field_not_found(ident)
+            }
+
+            fn unsupported_struct_version(&self, version: u32) -> Self::Error {
+                self.decoder.unsupported_struct_version(version)
+            }
+
+            #[inline]
+            fn load_array_len(&mut self) -> Result<usize, Self::Error> {
+                self.decoder.load_array_len()
+            }
+
+            #[inline]
+            fn load_raw<T: MemValue>(&mut self) -> Result<T, Self::Error> {
+                self.decoder.load_raw()
+            }
+
+            #[inline]
+            fn load_usize(&mut self) -> Result<usize, Self::Error> {
+                self.decoder.load_usize()
+            }
+
+            #[inline]
+            fn load_isize(&mut self) -> Result<isize, Self::Error> {
+                self.decoder.load_isize()
+            }
+
+            #[inline]
+            fn load_raw_slice<T: MemValue>(&mut self, values: &mut [T]) -> Result<(), Self::Error> {
+                self.decoder.load_raw_slice(values)
+            }
+
+            fn load_raw_vec<T: MemValue>(
+                &mut self,
+                len: usize,
+                values: &mut Vec<T>,
+            ) -> Result<(), Self::Error> {
+                self.decoder.load_raw_vec(len, values)
+            }
+
+            #[inline]
+            fn load_bytes(&mut self, bytes: &mut [u8]) -> Result<(), Self::Error> {
+                self.decoder.load_bytes(bytes)
+            }
+
+            #[inline]
+            fn check_bytes_len(&mut self, len: usize) -> Result<(), Self::Error> {
+                self.decoder.check_bytes_len(len)
+            }
 
-    #[inline]
-    fn load_bytes(&mut self, len: usize) -> Result<*const u8, Self::Error> {
-        let start = self.pos as usize;
-        let end = start + len;
-        if end > self.save.len() {
-            return Err(ReadError::UnexpectedEof);
-        }
-        self.pos = end as u32;
-        Ok(unsafe { self.save.as_ptr().add(start) })
-    }
+            #[inline]
+            fn start_struct(&mut self) -> Result<(), Self::Error> {
+                self.decoder.start_struct()
+            }
 
-    #[inline]
-    fn start_struct(&mut self) -> Result<(), Self::Error> {
-        let mut pos = self.load_raw::<u32>()? as usize;
-        let fields_len = *self.save.get(pos).ok_or(ReadError::UnexpectedEof)? as usize;
-        pos += 1;
+            #[inline]
+            fn end_struct(&mut self) -> Result<(), Self::Error> {
+                self.decoder.end_struct()
+            }
 
-        let mut fields = Vec::with_capacity(fields_len);
-        for _ in 0..fields_len {
-            let ident_bytes: &'a [u8] = unsafe { self.save.get_unchecked(pos..) };
-            let len = ident_bytes
-                .iter()
-                .position(|b| *b == 0)
-                .unwrap_or(ident_bytes.len());
-            let ident_bytes = &ident_bytes[..len];
-            let ident_end = pos + len + 1;
+            #[inline]
+            fn struct_version(&mut self) -> Result<u32, Self::Error> {
+                self.decoder.struct_version()
+            }
 
-            pos = ident_end + 4;
-            if pos > self.save.len() {
-                return Err(ReadError::UnexpectedEof);
+            #[inline]
+            fn legacy_enum_indices(&self) -> bool {
+                self.decoder.legacy_enum_indices()
             }
 
-            fields.push((ident_bytes, unsafe {
-                u32::read_le(self.save.as_ptr().add(ident_end) as *const u32)
-            }));
+            #[inline]
+            fn try_start_field_aliased(
+                &mut self,
+                ident: &[u8],
+                aliases: &[&[u8]],
+            ) -> Result<bool, Self::Error> {
+                self.decoder.try_start_field_aliased(ident, aliases)
+            }
         }
+    };
+}
+pub(super) use forward_read_savestate;
 
-        self.structs.push(StructInfo {
-            fields,
-            end: pos as u32,
-            cur_field: 0,
-        });
-        Ok(())
+// Used for checked savestates that will be saved to disk, and need compatibility across field order
+// changes, additions and deletions.
+pub struct PersistentReadSavestate<'a> {
+    info: SaveInfo,
+    decoder: PersistentDecoder<Cow<'a, [u8]>>,
+}
+
+impl<'a> PersistentReadSavestate<'a> {
+    pub fn new(save: &'a [u8]) -> Result<Self, ReadError> {
+        let (header, body) = decode_container(save)?;
+        Ok(PersistentReadSavestate {
+            info: header.info,
+            decoder: PersistentDecoder::new(body, header.format_version),
+        })
     }
 
-    #[inline]
-    fn end_struct(&mut self) -> Result<(), Self::Error> {
-        match self.structs.pop() {
-            Some(struct_info) => {
-                self.pos = struct_info.end;
-                Ok(())
-            }
-            None => Err(ReadError::NoStructPresent),
+    /// Reads a savestate saved before headers were added, which consists of a version 1 body
+    /// only; its info is left empty.
+    pub fn new_headerless(save: &'a [u8]) -> Self {
+        PersistentReadSavestate {
+            info: SaveInfo::default(),
+            decoder: PersistentDecoder::new(Cow::Borrowed(save), 1),
         }
     }
 
     #[inline]
-    fn start_field(&mut self, ident: &[u8]) -> Result<(), Self::Error> {
-        let cur_struct = self.structs.last_mut().ok_or(ReadError::NoStructPresent)?;
-        let mut i = cur_struct.cur_field;
-        let len = cur_struct.fields.len() as u8;
-        loop {
-            let field = cur_struct.fields[i as usize];
-            i += 1;
-            if i == len {
-                i = 0;
-            }
-            if field.0 == ident {
-                cur_struct.cur_field = i;
-                self.pos = field.1;
-                return Ok(());
-            }
-            if i == cur_struct.cur_field {
-                return Err(ReadError::FieldNotFound);
-            }
-        }
+    pub fn info(&self) -> &SaveInfo {
+        &self.info
     }
 }
 
+forward_read_savestate!(['a] PersistentReadSavestate<'a>);
+
 macro_rules! impl_loadable_raw {
     () => {};
 
-    ($ty: ty as bits $conv_ty: ty $(, $($others: tt)*)?) => {
+    ($ty: ty => $load: ident $(, $($others: tt)*)?) => {
         impl Loadable for $ty {
             #[inline]
             fn load<S: ReadSavestate>(save: &mut S) -> Result<Self, S::Error> {
-                save.load_raw::<$conv_ty>().map(|v| <$ty>::from_bits(v))
+                save.$load()
             }
         }
 
         impl LoadableInPlace for $ty {
             #[inline]
             fn load_in_place<S: ReadSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
-                *self = <$ty>::from_bits(save.load_raw::<$conv_ty>()?);
+                *self = save.$load()?;
                 Ok(())
             }
         }
@@ -250,30 +1226,27 @@ macro_rules! impl_loadable_raw {
         impl_loadable_raw!($($($others)*)*);
     };
 
-    ($ty: ty as $conv_ty: ty $(, $($others: tt)*)?) => {
+    ($ty: ty $(, $($others: tt)*)?) => {
         impl Loadable for $ty {
             #[inline]
             fn load<S: ReadSavestate>(save: &mut S) -> Result<Self, S::Error> {
-                save.load_raw::<$conv_ty>().map(|v| v as $ty)
+                save.load_raw()
             }
-        }
 
-        impl LoadableInPlace for $ty {
             #[inline]
-            fn load_in_place<S: ReadSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
-                *self = save.load_raw::<$conv_ty>()? as $ty;
-                Ok(())
+            fn load_array<S: ReadSavestate, const LEN: usize>(
+                save: &mut S,
+            ) -> Result<[Self; LEN], S::Error> {
+                let mut result = [0 as $ty; LEN];
+                save.load_raw_slice(&mut result)?;
+                Ok(result)
             }
-        }
 
-        impl_loadable_raw!($($($others)*)*);
-    };
-
-    ($ty: ty $(, $($others: tt)*)?) => {
-        impl Loadable for $ty {
             #[inline]
-            fn load<S: ReadSavestate>(save: &mut S) -> Result<Self, S::Error> {
-                save.load_raw()
+            fn load_vec<S: ReadSavestate>(len: usize, save: &mut S) -> Result<Vec<Self>, S::Error> {
+                let mut result = Vec::new();
+                save.load_raw_vec(len, &mut result)?;
+                Ok(result)
             }
         }
 
@@ -283,6 +1256,23 @@ macro_rules! impl_loadable_raw {
                 *self = save.load_raw()?;
                 Ok(())
             }
+
+            #[inline]
+            fn load_slice_in_place<S: ReadSavestate>(
+                slice: &mut [Self],
+                save: &mut S,
+            ) -> Result<(), S::Error> {
+                save.load_raw_slice(slice)
+            }
+
+            #[inline]
+            fn load_vec_in_place<S: ReadSavestate>(
+                vec: &mut Vec<Self>,
+                len: usize,
+                save: &mut S,
+            ) -> Result<(), S::Error> {
+                save.load_raw_vec(len, vec)
+            }
         }
 
         impl_loadable_raw!($($($others)*)*);
@@ -291,9 +1281,9 @@ macro_rules! impl_loadable_raw {
 
 #[rustfmt::skip]
 impl_loadable_raw!(
-    u8, u16, u32, u64, u128, usize as u32,
-    i8, i16, i32, i64, i128, isize as i32,
-    f32 as bits u32, f64 as bits u64
+    u8, u16, u32, u64, u128, usize => load_usize,
+    i8, i16, i32, i64, i128, isize => load_isize,
+    f32, f64
 );
 
 macro_rules! impl_loadable_tuples {
@@ -350,12 +1340,76 @@ where
 {
     #[inline]
     fn load<S: ReadSavestate>(save: &mut S) -> Result<Self, S::Error> {
-        let len = save.load_raw::<u32>()? as usize;
-        let mut result = Vec::with_capacity(len);
-        for _ in 0..len {
-            result.push(save.load()?);
+        let len = save.load_array_len()?;
+        T::load_vec(len, save)
+    }
+}
+
+impl<T> LoadableInPlace for Vec<T>
+where
+    T: Loadable + LoadableInPlace,
+{
+    #[inline]
+    fn load_in_place<S: ReadSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
+        let len = save.load_array_len()?;
+        T::load_vec_in_place(self, len, save)
+    }
+}
+
+impl<T> Loadable for Box<[T]>
+where
+    T: Loadable,
+{
+    #[inline]
+    fn load<S: ReadSavestate>(save: &mut S) -> Result<Self, S::Error> {
+        let len = save.load_array_len()?;
+        T::load_vec(len, save).map(Vec::into_boxed_slice)
+    }
+}
+
+impl<T> LoadableInPlace for Box<[T]>
+where
+    T: Loadable + LoadableInPlace,
+{
+    #[inline]
+    fn load_in_place<S: ReadSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
+        let len = save.load_array_len()?;
+        if len == self.len() {
+            save.load_slice(self)
+        } else {
+            *self = T::load_vec(len, save)?.into_boxed_slice();
+            Ok(())
         }
-        Ok(result)
+    }
+}
+
+impl Loadable for String {
+    #[inline]
+    fn load<S: ReadSavestate>(save: &mut S) -> Result<Self, S::Error> {
+        let len = save.load_array_len()?;
+        save.check_bytes_len(len)?;
+        let mut bytes = vec![0; len];
+        save.load_bytes(&mut bytes)?;
+        String::from_utf8(bytes).map_err(|_| save.invalid_utf8())
+    }
+}
+
+impl LoadableInPlace for String {
+    #[inline]
+    fn load_in_place<S: ReadSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
+        let len = save.load_array_len()?;
+        save.check_bytes_len(len)?;
+        // Zeroed bytes are valid UTF-8, and the string is cleared if the loaded ones aren't
+        let bytes = unsafe { self.as_mut_vec() };
+        bytes.clear();
+        bytes.resize(len, 0);
+        let result = save.load_bytes(bytes);
+        if result.is_ok() && str::from_utf8(bytes).is_ok() {
+            return Ok(());
+        }
+        bytes.clear();
+        result?;
+        Err(save.invalid_utf8())
     }
 }
 
@@ -365,11 +1419,7 @@ where
 {
     #[inline]
     fn load<S: ReadSavestate>(save: &mut S) -> Result<Self, S::Error> {
-        let mut result = [const { MaybeUninit::uninit() }; LEN];
-        for elem in &mut result {
-            *elem = MaybeUninit::new(save.load()?);
-        }
-        Ok(unsafe { MaybeUninit::array_assume_init(result) })
+        T::load_array(save)
     }
 }
 
@@ -379,10 +1429,7 @@ where
 {
     #[inline]
     fn load_in_place<S: ReadSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
-        for elem in self {
-            save.load_into(elem)?;
-        }
-        Ok(())
+        save.load_slice(self)
     }
 }
 
@@ -393,13 +1440,7 @@ where
 {
     #[inline]
     fn load<S: ReadSavestate>(save: &mut S) -> Result<Self, S::Error> {
-        let mut result = [const { MaybeUninit::uninit() }; LANES];
-        for elem in &mut result {
-            *elem = MaybeUninit::new(save.load()?);
-        }
-        Ok(Self::from_array(unsafe {
-            MaybeUninit::array_assume_init(result)
-        }))
+        T::load_array(save).map(Self::from_array)
     }
 }
 
@@ -410,28 +1451,23 @@ where
 {
     #[inline]
     fn load_in_place<S: ReadSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
-        for elem in self.as_mut_array() {
-            save.load_into(elem)?;
-        }
-        Ok(())
+        save.load_slice(self.as_mut_array())
     }
 }
 
 impl<const LEN: usize> Loadable for Bytes<LEN> {
     #[inline]
     fn load<S: ReadSavestate>(save: &mut S) -> Result<Self, S::Error> {
-        save.load_bytes(LEN)
-            .map(|v| unsafe { (*(v as *const Bytes<LEN>)).clone() })
+        let mut bytes = Bytes::new([0; LEN]);
+        save.load_bytes(&mut bytes[..])?;
+        Ok(bytes)
     }
 }
 
 impl<const LEN: usize> LoadableInPlace for Bytes<LEN> {
     #[inline]
     fn load_in_place<S: ReadSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
-        unsafe {
-            ptr::copy_nonoverlapping(save.load_bytes(LEN)?, self.as_mut_ptr(), LEN);
-        }
-        Ok(())
+        save.load_bytes(&mut self[..])
     }
 }
 
@@ -439,9 +1475,7 @@ impl<const LEN: usize> Loadable for OwnedBytesCellPtr<LEN> {
     #[inline]
     fn load<S: ReadSavestate>(save: &mut S) -> Result<Self, S::Error> {
         let bytes = OwnedBytesCellPtr::new_zeroed();
-        unsafe {
-            ptr::copy_nonoverlapping(save.load_bytes(LEN)?, bytes.as_mut_ptr(), LEN);
-        }
+        save.load_bytes(unsafe { bytes.as_mut_arr() })?;
         Ok(bytes)
     }
 }
@@ -449,9 +1483,116 @@ impl<const LEN: usize> Loadable for OwnedBytesCellPtr<LEN> {
 impl<const LEN: usize> LoadableInPlace for OwnedBytesCellPtr<LEN> {
     #[inline]
     fn load_in_place<S: ReadSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
-        unsafe {
-            ptr::copy_nonoverlapping(save.load_bytes(LEN)?, self.as_mut_ptr(), LEN);
-        }
+        save.load_bytes(unsafe { self.as_mut_arr() })
+    }
+}
+
+impl<const LEN: usize> Loadable for DirtyTracked<Bytes<LEN>> {
+    #[inline]
+    fn load<S: ReadSavestate>(save: &mut S) -> Result<Self, S::Error> {
+        save.load().map(DirtyTracked::new)
+    }
+}
+
+impl<const LEN: usize> LoadableInPlace for DirtyTracked<Bytes<LEN>> {
+    #[inline]
+    fn load_in_place<S: ReadSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
+        save.load_bytes(&mut self.inner_mut()[..])
+    }
+}
+
+impl<const LEN: usize> Loadable for DirtyTracked<OwnedBytesCellPtr<LEN>> {
+    #[inline]
+    fn load<S: ReadSavestate>(save: &mut S) -> Result<Self, S::Error> {
+        save.load().map(DirtyTracked::new)
+    }
+}
+
+impl<const LEN: usize> LoadableInPlace for DirtyTracked<OwnedBytesCellPtr<LEN>> {
+    #[inline]
+    fn load_in_place<S: ReadSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
+        save.load_bytes(unsafe { self.inner().as_mut_arr() })?;
+        self.dirty_pages().mark_all();
+        Ok(())
+    }
+}
+
+// Loads the length of a dynamically-sized value to be loaded in place, which can't be resized.
+#[inline]
+fn load_fixed_len<S: ReadSavestate>(save: &mut S, expected: usize) -> Result<(), S::Error> {
+    let len = save.load_array_len()?;
+    if len != expected {
+        return Err(save.invalid_length(expected as u64, len as u64));
+    }
+    Ok(())
+}
+
+impl Loadable for BoxedByteSlice {
+    #[inline]
+    fn load<S: ReadSavestate>(save: &mut S) -> Result<Self, S::Error> {
+        let len = save.load_array_len()?;
+        save.check_bytes_len(len)?;
+        let mut bytes = BoxedByteSlice::new_zeroed(len);
+        save.load_bytes(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl LoadableInPlace for BoxedByteSlice {
+    #[inline]
+    fn load_in_place<S: ReadSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
+        load_fixed_len(save, self.len())?;
+        save.load_bytes(self)
+    }
+}
+
+impl Loadable for OwnedByteSliceCellPtr {
+    #[inline]
+    fn load<S: ReadSavestate>(save: &mut S) -> Result<Self, S::Error> {
+        let len = save.load_array_len()?;
+        save.check_bytes_len(len)?;
+        let bytes = OwnedByteSliceCellPtr::new_zeroed(len);
+        save.load_bytes(unsafe { bytes.as_mut_slice() })?;
+        Ok(bytes)
+    }
+}
+
+impl LoadableInPlace for OwnedByteSliceCellPtr {
+    #[inline]
+    fn load_in_place<S: ReadSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
+        load_fixed_len(save, self.len())?;
+        save.load_bytes(unsafe { self.as_mut_slice() })
+    }
+}
+
+impl Loadable for DirtyTracked<BoxedByteSlice> {
+    #[inline]
+    fn load<S: ReadSavestate>(save: &mut S) -> Result<Self, S::Error> {
+        save.load().map(DirtyTracked::new)
+    }
+}
+
+impl LoadableInPlace for DirtyTracked<BoxedByteSlice> {
+    #[inline]
+    fn load_in_place<S: ReadSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
+        load_fixed_len(save, self.len())?;
+        save.load_bytes(self.inner_mut())
+    }
+}
+
+impl Loadable for DirtyTracked<OwnedByteSliceCellPtr> {
+    #[inline]
+    fn load<S: ReadSavestate>(save: &mut S) -> Result<Self, S::Error> {
+        save.load().map(DirtyTracked::new)
+    }
+}
+
+impl LoadableInPlace for DirtyTracked<OwnedByteSliceCellPtr> {
+    #[inline]
+    fn load_in_place<S: ReadSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
+        load_fixed_len(save, self.len())?;
+        save.load_bytes(unsafe { self.inner().as_mut_slice() })?;
+        self.dirty_pages().mark_all();
         Ok(())
     }
 }
@@ -555,8 +1696,365 @@ pub fn load_slice_in_place<S: ReadSavestate, T: LoadableInPlace>(
     slice: &mut [T],
     save: &mut S,
 ) -> Result<(), S::Error> {
-    for elem in slice {
-        save.load_into(elem)?;
+    save.load_slice(slice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        write_persistent, SaveInspector, Savestate, StreamReadSavestate, WriteOptions,
+        WriteSavestate,
+    };
+    use std::io::Cursor;
+
+    #[test]
+    fn untrusted_vec_lengths() {
+        let save = write_persistent(WriteOptions::default(), |writer| {
+            writer.store_array_len(usize::MAX / 64)
+        });
+        let mut reader = PersistentReadSavestate::new(&save).unwrap();
+        assert!(reader.load::<Vec<Vec<u8>>>().is_err());
+        let mut reader = PersistentReadSavestate::new(&save).unwrap();
+        assert!(reader.load::<Box<[bool]>>().is_err());
+        let mut reader = PersistentReadSavestate::new(&save).unwrap();
+        assert!(reader.load::<Vec<u64>>().is_err());
+        let mut reader = PersistentReadSavestate::new(&save).unwrap();
+        assert!(reader.load::<String>().is_err());
+
+        let mut vec = vec![true; 2];
+        let mut reader = PersistentReadSavestate::new(&save).unwrap();
+        assert!(reader.load_into(&mut vec).is_err());
+        assert!(vec.capacity() <= MAX_PREALLOCATED_LEN + 2);
+    }
+
+    #[derive(Savestate, Clone, Copy, PartialEq, Debug)]
+    enum Mode {
+        A = 5,
+        B = 2,
+        #[savestate(tag = 7)]
+        C,
+    }
+
+    #[derive(Savestate, PartialEq, Debug)]
+    struct Modes {
+        first: Mode,
+        second: Mode,
+    }
+
+    #[test]
+    fn legacy_enum_indices() {
+        // Version 1 stored variants as their index, untagged, followed by a field table made of a
+        // `u8` field count and each field's name and `u32` offset
+        let mut save = Vec::new();
+        Header {
+            format_version: 1,
+            flags: 0,
+            info: SaveInfo::new("test", 0),
+        }
+        .write(&mut save)
+        .unwrap();
+        let body_start = save.len();
+        save.extend_from_slice(&6_u32.to_le_bytes());
+        save.extend_from_slice(&[2, 1, 2]);
+        save.extend_from_slice(b"first\0");
+        save.extend_from_slice(&4_u32.to_le_bytes());
+        save.extend_from_slice(b"second\0");
+        save.extend_from_slice(&5_u32.to_le_bytes());
+
+        let mut reader = PersistentReadSavestate::new(&save).unwrap();
+        assert_eq!(
+            reader.load::<Modes>().unwrap(),
+            Modes {
+                first: Mode::C,
+                second: Mode::B,
+            }
+        );
+
+        // Indices past the last variant are invalid, even if they match a tag
+        save[body_start + 4] = 5;
+        let mut reader = PersistentReadSavestate::new(&save).unwrap();
+        assert_eq!(
+            reader.load::<Modes>().unwrap_err().kind,
+            ReadErrorKind::InvalidEnum
+        );
+    }
+
+    #[derive(Savestate, Debug)]
+    struct Narrow {
+        a: u16,
+        b: i8,
+        c: u8,
+        f: f32,
+        values: Vec<u16>,
+    }
+
+    #[derive(Savestate, PartialEq, Debug)]
+    struct Wide {
+        a: u32,
+        b: i64,
+        c: i16,
+        f: f64,
+        values: Vec<u32>,
+    }
+
+    #[derive(Savestate, Debug)]
+    struct NarrowHolder {
+        value: Narrow,
+    }
+
+    #[derive(Savestate, PartialEq, Debug)]
+    struct WideHolder {
+        value: Wide,
+    }
+
+    #[test]
+    fn widening() {
+        let save = write_persistent(WriteOptions::default(), |writer| {
+            writer.store(&mut NarrowHolder {
+                value: Narrow {
+                    a: 0xBEEF,
+                    b: -3,
+                    c: 200,
+                    f: 1.5,
+                    values: vec![1, 0xFFFF],
+                },
+            })
+        });
+        let mut reader = PersistentReadSavestate::new(&save).unwrap();
+        assert_eq!(
+            reader.load::<WideHolder>().unwrap(),
+            WideHolder {
+                value: Wide {
+                    a: 0xBEEF,
+                    b: -3,
+                    c: 200,
+                    f: 1.5,
+                    values: vec![1, 0xFFFF],
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn narrowing() {
+        let save = write_persistent(WriteOptions::default(), |writer| {
+            writer.store(&mut WideHolder {
+                value: Wide {
+                    a: 1,
+                    b: 2,
+                    c: 3,
+                    f: 4.0,
+                    values: vec![5],
+                },
+            })
+        });
+        let mut reader = PersistentReadSavestate::new(&save).unwrap();
+        let err = reader.load::<NarrowHolder>().unwrap_err();
+        assert_eq!(
+            err.kind,
+            ReadErrorKind::TypeMismatch {
+                expected: ValueType::of::<u16>(),
+                found: ValueType::of::<u32>(),
+            }
+        );
+        assert_eq!(err.path, "value.a");
+    }
+
+    // Loads a single raw `T` as a `U`.
+    fn load_raw_as<T: MemValue, U: MemValue>(value: T) -> Result<U, ReadError> {
+        let save = write_persistent(WriteOptions::default(), |writer| {
+            writer.store_raw(value);
+            Ok(())
+        });
+        PersistentReadSavestate::new(&save).unwrap().load_raw()
+    }
+
+    #[test]
+    fn signed_widening() {
+        // Unsigned values only fit in strictly wider signed types, and signed ones never become
+        // unsigned
+        assert_eq!(load_raw_as::<u8, i16>(200).unwrap(), 200);
+        assert_eq!(load_raw_as::<i8, i32>(-3).unwrap(), -3);
+        assert_eq!(load_raw_as::<i16, i64>(i16::MIN).unwrap(), i16::MIN as i64);
+        for err in [
+            load_raw_as::<u16, i16>(1).map(drop),
+            load_raw_as::<i8, u16>(1).map(drop),
+            load_raw_as::<i8, u8>(1).map(drop),
+            load_raw_as::<u32, f64>(1).map(drop),
+        ] {
+            assert!(matches!(
+                err.unwrap_err().kind,
+                ReadErrorKind::TypeMismatch { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn float_widening() {
+        assert_eq!(load_raw_as::<f32, f64>(-0.25).unwrap(), -0.25);
+        assert!(load_raw_as::<f32, f64>(f32::NAN).unwrap().is_nan());
+        assert_eq!(
+            load_raw_as::<f64, f32>(1.0).unwrap_err().kind,
+            ReadErrorKind::TypeMismatch {
+                expected: ValueType::of::<f32>(),
+                found: ValueType::of::<f64>(),
+            }
+        );
+    }
+
+    #[test]
+    fn nested_error_context() {
+        let save = write_persistent(WriteOptions::default(), |writer| {
+            writer.store(&mut WideHolder {
+                value: Wide {
+                    a: 1,
+                    b: 2,
+                    c: 3,
+                    f: 4.0,
+                    values: vec![5],
+                },
+            })
+        });
+        let inspector = SaveInspector::new(&save).unwrap();
+        let value = &inspector.root().fields.unwrap()[0].value;
+        let offset = value.fields.as_ref().unwrap()[0].value.offset as u64;
+
+        let err = PersistentReadSavestate::new(&save)
+            .unwrap()
+            .load::<NarrowHolder>()
+            .unwrap_err();
+        assert_eq!(err.path, "value.a");
+        assert_eq!(err.offset, Some(offset));
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "type mismatch (expected u16, found u32) (field `value.a`, offset {offset:#x})"
+            )
+        );
+    }
+
+    #[derive(Savestate, Debug)]
+    struct ByteVec {
+        bytes: Vec<u8>,
+    }
+
+    #[test]
+    fn out_of_bounds_error_context() {
+        // The length claims more elements than are present
+        let save = write_persistent(WriteOptions::default(), |writer| {
+            writer.start_struct()?;
+            writer.start_field(b"bytes")?;
+            writer.store_array_len(100)?;
+            writer.store_raw_slice(&[1_u8, 2, 3]);
+            writer.end_struct()
+        });
+        let inspector = SaveInspector::new(&save).unwrap();
+        // Past the length and the slice's type tag
+        let offset = inspector.root().fields.unwrap()[0].value.offset as u64 + 2;
+        let found = inspector.body().len() as u64 - offset;
+
+        let err = PersistentReadSavestate::new(&save)
+            .unwrap()
+            .load::<ByteVec>()
+            .unwrap_err();
+        assert_eq!(
+            err.kind,
+            ReadErrorKind::UnexpectedEof {
+                expected: 100,
+                found,
+            }
+        );
+        assert_eq!(err.path, "bytes");
+        assert_eq!(err.offset, Some(offset));
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "unexpected end of savestate (expected 100 bytes, found {found}) (field `bytes`, \
+                 offset {offset:#x})"
+            )
+        );
+    }
+
+    #[derive(Savestate, PartialEq, Debug)]
+    struct LegacyInner {
+        x: u16,
+    }
+
+    #[derive(Savestate, PartialEq, Debug)]
+    struct Legacy {
+        a: u32,
+        len: usize,
+        values: Vec<u16>,
+        inner: LegacyInner,
+        mode: Mode,
+    }
+
+    // A version 1 body holding a `Legacy`, laid out as before headers were added: structs start
+    // with the `u32` position of their field table, which holds a `u8` field count followed by each
+    // field's name and `u32` position, and values are stored untagged.
+    fn legacy_body() -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&34_u32.to_le_bytes());
+        body.extend_from_slice(&7_u32.to_le_bytes());
+        body.extend_from_slice(&3_u32.to_le_bytes());
+        body.extend_from_slice(&2_u32.to_le_bytes());
+        body.extend_from_slice(&[0x34, 0x12, 0x78, 0x56]);
+        body.extend_from_slice(&26_u32.to_le_bytes());
+        body.extend_from_slice(&0xABCD_u16.to_le_bytes());
+        body.extend_from_slice(&[1, b'x', 0]);
+        body.extend_from_slice(&24_u32.to_le_bytes());
+        body.push(1);
+        body.push(5);
+        for (ident, pos) in [
+            (&b"a"[..], 4_u32),
+            (b"len", 8),
+            (b"values", 12),
+            (b"inner", 20),
+            (b"mode", 33),
+        ] {
+            body.extend_from_slice(ident);
+            body.push(0);
+            body.extend_from_slice(&pos.to_le_bytes());
+        }
+        body
+    }
+
+    #[test]
+    fn legacy_fixture() {
+        let expected = Legacy {
+            a: 7,
+            len: 3,
+            values: vec![0x1234, 0x5678],
+            inner: LegacyInner { x: 0xABCD },
+            mode: Mode::B,
+        };
+        let body = legacy_body();
+        let mut save = Vec::new();
+        Header {
+            format_version: 1,
+            flags: 0,
+            info: SaveInfo::new("test", 0),
+        }
+        .write(&mut save)
+        .unwrap();
+        save.extend_from_slice(&body);
+
+        let mut reader = PersistentReadSavestate::new(&save).unwrap();
+        assert_eq!(reader.load::<Legacy>().unwrap(), expected);
+        let mut reader = StreamReadSavestate::new(Cursor::new(&save)).unwrap();
+        assert_eq!(reader.load::<Legacy>().unwrap(), expected);
+
+        let mut reader = PersistentReadSavestate::new_headerless(&body);
+        assert_eq!(reader.info(), &SaveInfo::default());
+        assert_eq!(reader.load::<Legacy>().unwrap(), expected);
+        let mut reader = StreamReadSavestate::new_headerless(Cursor::new(&body)).unwrap();
+        assert_eq!(reader.load::<Legacy>().unwrap(), expected);
+
+        // Headerless savestates need to be opened explicitly
+        assert_eq!(
+            PersistentReadSavestate::new(&body).err().unwrap().kind,
+            ReadErrorKind::InvalidMagic
+        );
     }
-    Ok(())
 }
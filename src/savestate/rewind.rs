@@ -0,0 +1,280 @@
+use super::{
+    apply_delta, encode_delta, restore_from_deltas, LoadableInPlace, ReadSavestate, Storable,
+    TransientReadSavestate, TransientWriteSavestate, WriteSavestate,
+};
+use std::{collections::VecDeque, marker::PhantomData, mem};
+
+#[derive(Clone, Copy, Debug)]
+pub struct RewindOptions {
+    /// Number of frames between captured snapshots.
+    pub interval: u32,
+    /// Maximum number of bytes taken by stored snapshots; the oldest ones are evicted past it,
+    /// though the most recent one is always kept.
+    pub memory_budget: usize,
+    /// Maximum number of snapshots per keyframe, including it; the others are stored as deltas
+    /// against the snapshot before them, and restoring one needs to apply all deltas since the
+    /// last keyframe.
+    pub keyframe_interval: usize,
+}
+
+impl Default for RewindOptions {
+    fn default() -> Self {
+        RewindOptions {
+            interval: 6,
+            memory_budget: 256 << 20,
+            keyframe_interval: 30,
+        }
+    }
+}
+
+struct Snapshot {
+    // Either a full transient savestate, or a delta against the previous snapshot's one.
+    data: Vec<u8>,
+    keyframe: bool,
+}
+
+// A history of transient savestates of a root value, captured periodically and restored in reverse
+// order to step backwards in time. Snapshots are only ever loaded as the type they were stored
+// from, which keeps transient reads in bounds.
+pub struct Rewind<T> {
+    options: RewindOptions,
+    frames_until_capture: u32,
+    snapshots: VecDeque<Snapshot>,
+    memory_used: usize,
+    // The full savestate of the most recent snapshot, that new deltas are encoded against.
+    latest: Vec<u8>,
+    scratch: Vec<u8>,
+    _root: PhantomData<fn(&mut T)>,
+}
+
+impl<T: Storable + LoadableInPlace> Rewind<T> {
+    pub fn new(options: RewindOptions) -> Self {
+        Rewind {
+            options,
+            frames_until_capture: 0,
+            snapshots: VecDeque::new(),
+            memory_used: 0,
+            latest: Vec::new(),
+            scratch: Vec::new(),
+            _root: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn options(&self) -> &RewindOptions {
+        &self.options
+    }
+
+    // Takes effect for the following captures, and evicts snapshots if the memory budget shrank.
+    pub fn set_options(&mut self, options: RewindOptions) {
+        self.options = options;
+        self.frames_until_capture = self
+            .frames_until_capture
+            .min(options.interval.saturating_sub(1));
+        self.evict();
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    #[inline]
+    pub fn memory_used(&self) -> usize {
+        self.memory_used
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.memory_used = 0;
+        self.latest.clear();
+        self.frames_until_capture = 0;
+    }
+
+    // Should be called once per emulated frame; captures a snapshot every `interval` frames,
+    // returning whether one was taken.
+    pub fn frame(&mut self, root: &mut T) -> bool {
+        if self.frames_until_capture != 0 {
+            self.frames_until_capture -= 1;
+            return false;
+        }
+        self.capture(root);
+        true
+    }
+
+    pub fn capture(&mut self, root: &mut T) {
+        self.frames_until_capture = self.options.interval.saturating_sub(1);
+
+        self.scratch.clear();
+        let Ok(()) = TransientWriteSavestate::new(&mut self.scratch).store(root);
+
+        let since_keyframe = self
+            .snapshots
+            .iter()
+            .rev()
+            .position(|snapshot| snapshot.keyframe);
+        let mut snapshot = Snapshot {
+            data: Vec::new(),
+            keyframe: since_keyframe.is_none_or(|i| i + 1 >= self.options.keyframe_interval),
+        };
+        if !snapshot.keyframe {
+            encode_delta(&self.latest, &self.scratch, &mut snapshot.data);
+            // Deltas of heavily changed states can end up larger than the states themselves
+            snapshot.keyframe = snapshot.data.len() >= self.scratch.len();
+        }
+        if snapshot.keyframe {
+            snapshot.data.clear();
+            snapshot.data.extend_from_slice(&self.scratch);
+        }
+        snapshot.data.shrink_to_fit();
+
+        mem::swap(&mut self.latest, &mut self.scratch);
+        self.memory_used += snapshot.data.len();
+        self.snapshots.push_back(snapshot);
+        self.evict();
+    }
+
+    // Restores the most recent snapshot into `root` and removes it from the history, returning
+    // whether there was one.
+    pub fn step_back(&mut self, root: &mut T) -> bool {
+        let Some(snapshot) = self.snapshots.pop_back() else {
+            return false;
+        };
+        self.memory_used -= snapshot.data.len();
+        self.frames_until_capture = self.options.interval.saturating_sub(1);
+
+        // The savestate was produced by a `TransientWriteSavestate` storing a `T`
+        let Ok(()) = unsafe { TransientReadSavestate::new(&self.latest) }.load_into(root);
+
+        // Rebuild the new most recent snapshot's savestate
+        mem::swap(&mut self.latest, &mut self.scratch);
+        match self
+            .snapshots
+            .iter()
+            .rposition(|snapshot| snapshot.keyframe)
+        {
+            Some(keyframe_index) => restore_from_deltas(
+                &self.snapshots[keyframe_index].data,
+                self.snapshots
+                    .range(keyframe_index + 1..)
+                    .map(|snapshot| &snapshot.data[..]),
+                &mut self.latest,
+            )
+            .expect("rewind deltas should match the savestates they were encoded against"),
+            None => self.latest.clear(),
+        }
+        true
+    }
+
+    fn evict(&mut self) {
+        while self.memory_used > self.options.memory_budget && self.snapshots.len() > 1 {
+            // The oldest snapshot is always a keyframe; if the next one is a delta, it needs to be
+            // turned into a keyframe itself
+            let mut oldest = self.snapshots.pop_front().unwrap();
+            self.memory_used -= oldest.data.len();
+            let next = self.snapshots.front_mut().unwrap();
+            if !next.keyframe {
+                apply_delta(&mut oldest.data, &next.data)
+                    .expect("rewind deltas should match the savestates they were encoded against");
+                self.memory_used -= next.data.len();
+                self.memory_used += oldest.data.len();
+                next.data = oldest.data;
+                next.keyframe = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Savestate;
+
+    #[derive(Savestate, Clone, PartialEq, Debug)]
+    struct State {
+        frame: u32,
+        ram: Vec<u8>,
+    }
+
+    impl State {
+        fn new() -> Self {
+            State {
+                frame: 0,
+                ram: vec![0; 0x400],
+            }
+        }
+
+        fn step(&mut self) {
+            self.frame += 1;
+            let len = self.ram.len();
+            self.ram[(self.frame as usize * 37) % len] ^= self.frame as u8;
+        }
+    }
+
+    // Captures `frames` snapshots, returning the rewind history along with the state at each one.
+    fn capture(options: RewindOptions, frames: usize) -> (Rewind<State>, Vec<State>) {
+        let mut rewind = Rewind::new(options);
+        let mut state = State::new();
+        let mut history = Vec::new();
+        for _ in 0..frames {
+            state.step();
+            assert!(rewind.frame(&mut state));
+            history.push(state.clone());
+        }
+        (rewind, history)
+    }
+
+    // Steps back through every snapshot, checking that they match the end of `history`.
+    fn check_step_back(rewind: &mut Rewind<State>, history: &[State]) {
+        let mut state = State::new();
+        for expected in history.iter().rev().take(rewind.len()) {
+            assert!(rewind.step_back(&mut state));
+            assert_eq!(&state, expected);
+        }
+        assert!(!rewind.step_back(&mut state));
+        assert_eq!(rewind.memory_used(), 0);
+    }
+
+    #[test]
+    fn step_back() {
+        let options = RewindOptions {
+            interval: 1,
+            memory_budget: usize::MAX,
+            keyframe_interval: 4,
+        };
+        let (mut rewind, history) = capture(options, 10);
+        assert_eq!(rewind.len(), 10);
+        check_step_back(&mut rewind, &history);
+    }
+
+    #[test]
+    fn evict() {
+        // Only the first snapshot is a keyframe, so evicting it turns the next delta into one
+        let options = RewindOptions {
+            interval: 1,
+            memory_budget: TransientWriteSavestate::measure(&mut State::new()) + 0x20,
+            keyframe_interval: usize::MAX,
+        };
+        let (mut rewind, history) = capture(options, 10);
+        assert!((2..10).contains(&rewind.len()));
+        assert!(rewind.memory_used() <= options.memory_budget);
+        assert!(rewind.snapshots[0].keyframe);
+        check_step_back(&mut rewind, &history);
+    }
+
+    #[test]
+    fn capture_interval() {
+        let mut rewind = Rewind::new(RewindOptions {
+            interval: 3,
+            ..Default::default()
+        });
+        let mut state = State::new();
+        let captured = (0..7).map(|_| rewind.frame(&mut state)).collect::<Vec<_>>();
+        assert_eq!(captured, [true, false, false, true, false, false, true]);
+    }
+}
@@ -0,0 +1,472 @@
+use super::{
+    checksum::{crc32_patch, Crc32},
+    header::{read_error, read_exact, Header, FLAG_CHECKSUM, FLAG_COMPRESSED, FORMAT_VERSION},
+    read::{forward_read_savestate, BodySource, PersistentDecoder},
+    varint::write_varint,
+    write::{forward_write_savestate, BodySink, PersistentEncoder},
+    ReadError, ReadErrorKind, ReadSavestate, SaveInfo, WriteError, WriteErrorKind, WriteOptions,
+    WriteSavestate,
+};
+use crate::MemValue;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+const BUFFER_LEN: usize = 0x1_0000;
+// Data preceding a refill position that `StreamReadSavestate` keeps buffered
+const BUFFER_KEEP_LEN: usize = BUFFER_LEN / 4;
+
+// Writes the same format as `PersistentWriteSavestate` directly to a stream, seeking back to fill
+// in struct field table offsets. Compression is unsupported, as it needs the whole body upfront;
+// checksums are computed while writing, and corrected for patched offsets at the end.
+pub struct StreamWriteSavestate<W: Write + Seek> {
+    encoder: PersistentEncoder<StreamSink<W>>,
+}
+
+// Body of a savestate being written to a stream through a buffer.
+struct StreamSink<W: Write + Seek> {
+    writer: W,
+    body_start: u64,
+    // CRC-32 of the data written to `writer` so far if checksumming, and the body positions and
+    // values of the offsets patched since then.
+    crc: Option<Crc32>,
+    crc_patches: Vec<(u64, [u8; 8])>,
+    // Data that hasn't been written to `writer` yet, starting at `buffer_pos` inside the body.
+    buffer: Vec<u8>,
+    buffer_pos: u64,
+    // I/O errors can't be reported from `store_raw`/`store_byte_slice`, so the first one is kept
+    // around until the next fallible operation.
+    error: Option<io::ErrorKind>,
+}
+
+impl<W: Write + Seek> StreamWriteSavestate<W> {
+    pub fn new(mut writer: W, info: &SaveInfo, options: WriteOptions) -> Result<Self, WriteError> {
+        if options.compression.is_some() {
+            return Err(WriteErrorKind::UnsupportedCompression.into());
+        }
+        let mut header = Vec::new();
+        Header {
+            format_version: FORMAT_VERSION,
+            flags: if options.checksum { FLAG_CHECKSUM } else { 0 },
+            info: info.clone(),
+        }
+        .write(&mut header)?;
+        writer.write_all(&header).map_err(io_error)?;
+        let body_start = writer.stream_position().map_err(io_error)?;
+        Ok(StreamWriteSavestate {
+            encoder: PersistentEncoder::new(StreamSink {
+                writer,
+                body_start,
+                crc: options.checksum.then(|| {
+                    let mut crc = Crc32::new();
+                    crc.update(&header);
+                    crc
+                }),
+                crc_patches: Vec::new(),
+                buffer: Vec::with_capacity(BUFFER_LEN),
+                buffer_pos: 0,
+                error: None,
+            }),
+        })
+    }
+
+    // Must be called once all values have been stored; returns the underlying writer.
+    pub fn finish(mut self) -> Result<W, WriteError> {
+        self.encoder.check_finished()?;
+        self.encoder.sink.flush();
+        self.encoder.check_error()?;
+        let mut sink = self.encoder.sink;
+        if let Some(crc) = sink.crc {
+            let mut checksum = crc.finish();
+            for (pos, bytes) in &sink.crc_patches {
+                checksum ^= crc32_patch(bytes, sink.buffer_pos - pos - bytes.len() as u64);
+            }
+            sink.writer
+                .write_all(&checksum.to_le_bytes())
+                .map_err(io_error)?;
+        }
+        sink.writer.flush().map_err(io_error)?;
+        Ok(sink.writer)
+    }
+}
+
+forward_write_savestate!([W: Write + Seek] StreamWriteSavestate<W>);
+
+impl<W: Write + Seek> StreamSink<W> {
+    fn flush(&mut self) {
+        if let Some(crc) = &mut self.crc {
+            crc.update(&self.buffer);
+        }
+        if self.error.is_none() {
+            if let Err(err) = self.writer.write_all(&self.buffer) {
+                self.error = Some(err.kind());
+            }
+        }
+        self.buffer_pos += self.buffer.len() as u64;
+        self.buffer.clear();
+    }
+}
+
+impl<W: Write + Seek> BodySink for StreamSink<W> {
+    #[inline]
+    fn body_pos(&self) -> u64 {
+        self.buffer_pos + self.buffer.len() as u64
+    }
+
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+        if self.buffer.len() >= BUFFER_LEN {
+            self.flush();
+        }
+    }
+
+    // Writes large byte arrays directly, instead of copying them to the buffer first.
+    #[inline]
+    fn write_bulk(&mut self, bytes: &[u8]) {
+        if bytes.len() >= BUFFER_LEN {
+            self.flush();
+            if let Some(crc) = &mut self.crc {
+                crc.update(bytes);
+            }
+            self.buffer_pos += bytes.len() as u64;
+            if self.error.is_none() {
+                if let Err(err) = self.writer.write_all(bytes) {
+                    self.error = Some(err.kind());
+                }
+            }
+        } else {
+            self.write(bytes);
+        }
+    }
+
+    #[inline]
+    fn write_varint(&mut self, value: u64) {
+        write_varint(&mut self.buffer, value);
+        if self.buffer.len() >= BUFFER_LEN {
+            self.flush();
+        }
+    }
+
+    fn patch(&mut self, pos: u64, bytes: [u8; 8]) -> Result<(), WriteErrorKind> {
+        if let Some(buffer_offset) = pos.checked_sub(self.buffer_pos) {
+            let buffer_offset = buffer_offset as usize;
+            self.buffer[buffer_offset..buffer_offset + bytes.len()].copy_from_slice(&bytes);
+            return Ok(());
+        }
+
+        // The placeholder was already checksummed as zeros
+        if self.crc.is_some() {
+            self.crc_patches.push((pos, bytes));
+        }
+        self.flush();
+        self.check_error()?;
+        let result = (|| {
+            self.writer.seek(SeekFrom::Start(self.body_start + pos))?;
+            self.writer.write_all(&bytes)?;
+            self.writer
+                .seek(SeekFrom::Start(self.body_start + self.buffer_pos))?;
+            Ok(())
+        })();
+        result.map_err(|err: io::Error| WriteErrorKind::Io(err.kind()))
+    }
+
+    #[inline]
+    fn check_error(&self) -> Result<(), WriteErrorKind> {
+        match self.error {
+            Some(kind) => Err(WriteErrorKind::Io(kind)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[inline]
+fn io_error(err: io::Error) -> WriteError {
+    WriteErrorKind::Io(err.kind()).into()
+}
+
+// Reads the format written by `PersistentWriteSavestate` from a stream, only keeping the field
+// tables of the structs currently being loaded in memory. Compressed savestates are unsupported, as
+// they can't be seeked through.
+pub struct StreamReadSavestate<R: Read + Seek> {
+    info: SaveInfo,
+    decoder: PersistentDecoder<StreamBody<R>>,
+}
+
+// Body of a savestate inside a stream, read through a buffer.
+struct StreamBody<R: Read + Seek> {
+    reader: R,
+    body_start: u64,
+    body_len: u64,
+    // Cached data, starting at `buffer_pos` inside the body.
+    buffer: Vec<u8>,
+    buffer_pos: u64,
+    // Position of `reader` inside the body, if known, to avoid seeking when it's already there.
+    reader_pos: Option<u64>,
+}
+
+impl<R: Read + Seek> StreamBody<R> {
+    fn seek_reader(&mut self, pos: u64) -> Result<(), ReadError> {
+        if self.reader_pos != Some(pos) {
+            self.reader
+                .seek(SeekFrom::Start(self.body_start + pos))
+                .map_err(read_error)?;
+        }
+        // Only known again once the following read succeeds
+        self.reader_pos = None;
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> BodySource for StreamBody<R> {
+    #[inline]
+    fn body_len(&self) -> u64 {
+        self.body_len
+    }
+
+    fn read_in_bounds(&mut self, pos: u64, bytes: &mut [u8]) -> Result<(), ReadError> {
+        let end = pos + bytes.len() as u64;
+
+        let buffer_end = self.buffer_pos + self.buffer.len() as u64;
+        if pos < self.buffer_pos || end > buffer_end {
+            if bytes.len() >= BUFFER_LEN {
+                self.seek_reader(pos)?;
+                read_exact(&mut self.reader, bytes)?;
+                self.reader_pos = Some(end);
+                return Ok(());
+            }
+
+            // Keep the buffered data from shortly before `pos` onwards, if any, and only read the
+            // rest; struct fields are read right after their field table, which follows them
+            let start = if (self.buffer_pos..=buffer_end).contains(&pos) {
+                let start = pos
+                    .saturating_sub(BUFFER_KEEP_LEN as u64)
+                    .max(end.saturating_sub(BUFFER_LEN as u64))
+                    .max(self.buffer_pos);
+                self.buffer.drain(..(start - self.buffer_pos) as usize);
+                start
+            } else {
+                self.buffer.clear();
+                pos
+            };
+            let kept = self.buffer.len();
+            self.buffer_pos = start;
+            let len = (self.body_len - start).min(BUFFER_LEN as u64) as usize;
+            self.buffer.resize(len, 0);
+            let result = self
+                .seek_reader(start + kept as u64)
+                .and_then(|_| read_exact(&mut self.reader, &mut self.buffer[kept..]));
+            if let Err(err) = result {
+                self.buffer.clear();
+                return Err(err);
+            }
+            self.reader_pos = Some(start + len as u64);
+        }
+
+        let start = (pos - self.buffer_pos) as usize;
+        bytes.copy_from_slice(&self.buffer[start..start + bytes.len()]);
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> StreamReadSavestate<R> {
+    pub fn new(mut reader: R) -> Result<Self, ReadError> {
+        let header_start = reader.stream_position().map_err(read_error)?;
+        let (header, header_len) = Header::read(&mut reader)?;
+        if header.flags & FLAG_COMPRESSED != 0 {
+            return Err(ReadErrorKind::UnsupportedCompression.into());
+        }
+
+        let body_start = header_start + header_len as u64;
+        let mut body_end = reader.seek(SeekFrom::End(0)).map_err(read_error)?;
+
+        if header.flags & FLAG_CHECKSUM != 0 {
+            body_end = body_end
+                .checked_sub(4)
+                .filter(|end| *end >= body_start)
+                .ok_or(ReadErrorKind::UnexpectedEof {
+                    expected: 4,
+                    found: body_end.saturating_sub(body_start),
+                })?;
+
+            reader
+                .seek(SeekFrom::Start(header_start))
+                .map_err(read_error)?;
+            let mut crc = Crc32::new();
+            let mut buffer = vec![0; BUFFER_LEN];
+            let mut remaining = body_end - header_start;
+            while remaining != 0 {
+                let chunk = &mut buffer[..remaining.min(BUFFER_LEN as u64) as usize];
+                read_exact(&mut reader, chunk)?;
+                crc.update(chunk);
+                remaining -= chunk.len() as u64;
+            }
+            let mut checksum = [0; 4];
+            read_exact(&mut reader, &mut checksum)?;
+            if crc.finish() != u32::from_le_bytes(checksum) {
+                return Err(ReadErrorKind::ChecksumMismatch.into());
+            }
+        }
+
+        Ok(Self::with_body(
+            reader,
+            header.info,
+            header.format_version,
+            body_start,
+            body_end,
+        ))
+    }
+
+    /// Reads a savestate saved before headers were added, which consists of a version 1 body
+    /// only, starting at the reader's current position; its info is left empty.
+    pub fn new_headerless(mut reader: R) -> Result<Self, ReadError> {
+        let body_start = reader.stream_position().map_err(read_error)?;
+        let body_end = reader.seek(SeekFrom::End(0)).map_err(read_error)?;
+        Ok(Self::with_body(
+            reader,
+            SaveInfo::default(),
+            1,
+            body_start,
+            body_end,
+        ))
+    }
+
+    fn with_body(
+        reader: R,
+        info: SaveInfo,
+        format_version: u16,
+        body_start: u64,
+        body_end: u64,
+    ) -> Self {
+        StreamReadSavestate {
+            info,
+            decoder: PersistentDecoder::new(
+                StreamBody {
+                    reader,
+                    body_start,
+                    body_len: body_end.saturating_sub(body_start),
+                    buffer: Vec::with_capacity(BUFFER_LEN),
+                    buffer_pos: 0,
+                    reader_pos: None,
+                },
+                format_version,
+            ),
+        }
+    }
+
+    #[inline]
+    pub fn info(&self) -> &SaveInfo {
+        &self.info
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.decoder.body.reader
+    }
+}
+
+forward_read_savestate!([R: Read + Seek] StreamReadSavestate<R>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_info, write_persistent, Savestate};
+    use std::io::Cursor;
+
+    #[derive(Savestate)]
+    struct Inner {
+        values: Vec<u32>,
+        bytes: Vec<u8>,
+    }
+
+    #[derive(Savestate)]
+    struct Outer {
+        small: u8,
+        // Structs ending after their start was flushed need their offsets patched in the stream
+        inner: [Inner; 3],
+        flag: bool,
+    }
+
+    #[test]
+    fn same_as_persistent() {
+        let mut value = Outer {
+            small: 1,
+            inner: core::array::from_fn(|i| Inner {
+                values: (0..10000 * i as u32).collect(),
+                bytes: vec![i as u8; 0x18000 * i],
+            }),
+            flag: true,
+        };
+        for checksum in [false, true] {
+            let options = WriteOptions {
+                checksum,
+                compression: None,
+            };
+            let expected = write_persistent(options, |writer| writer.store(&mut value));
+
+            let mut writer =
+                StreamWriteSavestate::new(Cursor::new(Vec::new()), &test_info(), options).unwrap();
+            writer.store(&mut value).unwrap();
+            let save = writer.finish().unwrap().into_inner();
+            assert!(save == expected);
+        }
+    }
+
+    struct CountingReader {
+        inner: Cursor<Vec<u8>>,
+        seeks: usize,
+        read_len: usize,
+    }
+
+    impl Read for CountingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = self.inner.read(buf)?;
+            self.read_len += len;
+            Ok(len)
+        }
+    }
+
+    impl Seek for CountingReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.seeks += 1;
+            self.inner.seek(pos)
+        }
+    }
+
+    #[derive(Savestate, PartialEq, Debug)]
+    struct Small {
+        a: u32,
+        b: u16,
+    }
+
+    #[test]
+    fn buffer_reuse() {
+        let mut value: Vec<Small> = (0..50000).map(|i| Small { a: i, b: i as u16 }).collect();
+        let save = write_persistent(WriteOptions::default(), |writer| writer.store(&mut value));
+
+        let save_len = save.len();
+        let mut reader = StreamReadSavestate::new(CountingReader {
+            inner: Cursor::new(save),
+            seeks: 0,
+            read_len: 0,
+        })
+        .unwrap();
+        assert_eq!(reader.load::<Vec<Small>>().unwrap(), value);
+        let reader = reader.into_inner();
+        // Field tables crossing the end of the buffer shouldn't cause the fields before them to be
+        // read again
+        assert!(reader.seeks < 8);
+        assert!(reader.read_len < save_len + save_len / 16);
+    }
+
+    #[test]
+    fn reject_compression() {
+        let options = WriteOptions {
+            checksum: false,
+            compression: Some(1),
+        };
+        let err =
+            StreamWriteSavestate::new(Cursor::new(Vec::new()), &SaveInfo::new("test", 0), options)
+                .err()
+                .unwrap();
+        assert_eq!(err.kind, WriteErrorKind::UnsupportedCompression);
+    }
+}
@@ -0,0 +1,1016 @@
+use super::{
+    read::{field_path, FieldInfo, StructInfo as ReadStructInfo},
+    write::{field_path as write_field_path, StructInfo as WriteStructInfo},
+    ReadError, ReadErrorKind, ReadSavestate, SaveInfo, ValueKind, ValueType, WriteError,
+    WriteErrorKind, WriteSavestate,
+};
+use crate::MemValue;
+use core::fmt::Write as _;
+
+const TEXT_FORMAT_VERSION: u64 = 1;
+const INDENT: &str = "    ";
+// Lines holding more than one value get wrapped before exceeding this length.
+const MAX_LINE_LEN: usize = 100;
+const BYTES_PER_LINE: usize = 32;
+// Deeper structs are rejected, to bound recursion on malformed input.
+const MAX_DEPTH: usize = 256;
+
+// Writes a human-readable form of the struct tree, meant for bug reports, golden tests and manual
+// editing; it's loaded back by `TextReadSavestate`, with the same compatibility guarantees as
+// persistent savestates. Raw values are written as Rust-like literals with a type suffix (i.e.
+// `0x1f_u16`, `-3_i8`, `1.5_f32`), with hexadecimal literals of float types holding their bit
+// patterns (only used for NaNs), byte blobs as `bytes(...)` in hexadecimal, and array lengths as
+// `len(...)`:
+//
+// EmuState(format: 1, emu_id: "dust", emu_version: 1, timestamp: 1700000000)
+// {
+//     version(1)
+//     regs: 0x0_u32 0x1_u32,
+//     vram: bytes(00ff),
+//     inner: {
+//         values: len(2) 0x5_u8 0x6_u8,
+//     },
+// }
+//
+// Commas are optional when parsing, and `//` starts a comment.
+pub struct TextWriteSavestate<'a> {
+    save: &'a mut String,
+    line_start: usize,
+    at_line_start: bool,
+    line_has_values: bool,
+    structs: Vec<WriteStructInfo>,
+}
+
+impl<'a> TextWriteSavestate<'a> {
+    pub fn new(save: &'a mut String, info: &SaveInfo) -> Self {
+        save.push_str("EmuState(format: ");
+        write!(save, "{TEXT_FORMAT_VERSION}, emu_id: ").unwrap();
+        write_string(save, info.emu_id.as_bytes());
+        write!(
+            save,
+            ", emu_version: {}, timestamp: {})",
+            info.emu_version, info.timestamp
+        )
+        .unwrap();
+        save.push('\n');
+        let line_start = save.len();
+        TextWriteSavestate {
+            save,
+            line_start,
+            at_line_start: true,
+            line_has_values: false,
+            structs: Vec::new(),
+        }
+    }
+
+    // Must be called once all values have been stored.
+    pub fn finish(self) -> Result<(), WriteError> {
+        if !self.structs.is_empty() {
+            return Err(self.error(WriteErrorKind::UnfinishedStruct));
+        }
+        self.save.push('\n');
+        Ok(())
+    }
+
+    fn error(&self, kind: WriteErrorKind) -> WriteError {
+        WriteError {
+            kind,
+            path: write_field_path(&self.structs),
+            offset: Some(self.save.len() as u64),
+        }
+    }
+
+    fn new_line(&mut self, depth: usize) {
+        self.save.push('\n');
+        self.line_start = self.save.len();
+        for _ in 0..depth {
+            self.save.push_str(INDENT);
+        }
+        self.at_line_start = true;
+        self.line_has_values = false;
+    }
+
+    fn write_token(&mut self, token: &str) {
+        if !self.at_line_start {
+            let line_len = self.save.len() - self.line_start;
+            if self.line_has_values && line_len + 1 + token.len() > MAX_LINE_LEN {
+                self.new_line(self.structs.len() + 1);
+            } else {
+                self.save.push(' ');
+            }
+        }
+        self.save.push_str(token);
+        self.at_line_start = false;
+        self.line_has_values = true;
+    }
+
+    // Separates a struct's previous field from whatever follows.
+    fn end_field(&mut self) {
+        if self
+            .structs
+            .last()
+            .is_some_and(|struct_info| !struct_info.fields.is_empty())
+        {
+            self.save.push(',');
+        }
+    }
+}
+
+fn write_string(save: &mut String, bytes: &[u8]) {
+    save.push('"');
+    for &byte in bytes {
+        match byte {
+            b'"' => save.push_str("\\\""),
+            b'\\' => save.push_str("\\\\"),
+            0x20..=0x7E => save.push(byte as char),
+            _ => write!(save, "\\x{byte:02x}").unwrap(),
+        }
+    }
+    save.push('"');
+}
+
+fn is_ident(bytes: &[u8]) -> bool {
+    bytes
+        .first()
+        .is_some_and(|byte| byte.is_ascii_alphabetic() || *byte == b'_')
+        && bytes
+            .iter()
+            .all(|byte| byte.is_ascii_alphanumeric() || *byte == b'_')
+}
+
+fn format_raw(ty: ValueType, bytes: [u8; 16]) -> String {
+    let bits = ty.size * 8;
+    match ty.kind {
+        ValueKind::Unsigned => format!("{:#x}_{ty}", u128::from_le_bytes(bytes)),
+        ValueKind::Signed => {
+            let shift = 128 - bits;
+            format!("{}_{ty}", i128::from_le_bytes(bytes) << shift >> shift)
+        }
+        ValueKind::Float => {
+            let (value, is_nan) = if ty.size == 4 {
+                let value = f32::from_le_bytes(bytes[..4].try_into().unwrap());
+                (format!("{value:?}"), value.is_nan())
+            } else {
+                let value = f64::from_le_bytes(bytes[..8].try_into().unwrap());
+                (format!("{value:?}"), value.is_nan())
+            };
+            if is_nan {
+                // Keep the NaN's payload
+                format!("{:#x}_{ty}", u128::from_le_bytes(bytes))
+            } else {
+                format!("{value}_{ty}")
+            }
+        }
+    }
+}
+
+impl<'a> WriteSavestate for TextWriteSavestate<'a> {
+    type Error = WriteError;
+
+    const TRANSIENT: bool = false;
+
+    #[inline]
+    fn store_array_len(&mut self, len: usize) -> Result<(), Self::Error> {
+        self.write_token(&format!("len({len})"));
+        Ok(())
+    }
+
+    #[inline]
+    fn store_raw<T: MemValue>(&mut self, value: T) {
+        let mut bytes = [0; 16];
+        unsafe { value.write_le(bytes.as_mut_ptr() as *mut T) };
+        self.write_token(&format_raw(ValueType::of::<T>(), bytes));
+    }
+
+    fn store_raw_slice<T: MemValue>(&mut self, values: &[T]) {
+        for value in values {
+            self.store_raw(*value);
+        }
+    }
+
+    fn store_byte_slice(&mut self, bytes: &[u8]) {
+        let mut hex = String::with_capacity(BYTES_PER_LINE * 2);
+        if bytes.len() <= BYTES_PER_LINE {
+            hex.push_str("bytes(");
+            for byte in bytes {
+                write!(hex, "{byte:02x}").unwrap();
+            }
+            hex.push(')');
+            self.write_token(&hex);
+            return;
+        }
+
+        self.write_token("bytes(");
+        let depth = self.structs.len();
+        for chunk in bytes.chunks(BYTES_PER_LINE) {
+            hex.clear();
+            for byte in chunk {
+                write!(hex, "{byte:02x}").unwrap();
+            }
+            self.new_line(depth + 1);
+            self.save.push_str(&hex);
+        }
+        self.new_line(depth);
+        self.save.push(')');
+        self.at_line_start = false;
+    }
+
+    fn start_struct(&mut self) -> Result<(), Self::Error> {
+        self.write_token("{");
+        self.structs.push(WriteStructInfo {
+            start_pos: self.save.len() as u64,
+            version: 0,
+            fields: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn end_struct(&mut self) -> Result<(), Self::Error> {
+        if self.structs.is_empty() {
+            return Err(self.error(WriteErrorKind::NoStructPresent));
+        }
+        self.end_field();
+        self.structs.pop();
+        self.new_line(self.structs.len());
+        self.save.push('}');
+        self.at_line_start = false;
+        Ok(())
+    }
+
+    fn start_field(&mut self, ident: &'static [u8]) -> Result<(), Self::Error> {
+        if self.structs.is_empty() {
+            return Err(self.error(WriteErrorKind::NoStructPresent));
+        }
+        self.end_field();
+        self.new_line(self.structs.len());
+        if is_ident(ident) {
+            self.save.push_str(core::str::from_utf8(ident).unwrap());
+        } else {
+            write_string(self.save, ident);
+        }
+        self.save.push(':');
+        self.at_line_start = false;
+
+        let pos = self.save.len() as u64;
+        self.structs.last_mut().unwrap().fields.push((ident, pos));
+        Ok(())
+    }
+
+    fn set_struct_version(&mut self, version: u32) -> Result<(), Self::Error> {
+        let Some(cur_struct) = self.structs.last_mut() else {
+            return Err(self.error(WriteErrorKind::NoStructPresent));
+        };
+        cur_struct.version = version;
+        self.end_field();
+        self.new_line(self.structs.len());
+        write!(self.save, "version({version})").unwrap();
+        self.at_line_start = false;
+        Ok(())
+    }
+}
+
+enum TextValue {
+    Raw(ValueType, [u8; 16]),
+    Bytes(Vec<u8>),
+    ArrayLen(u64),
+    Struct(usize),
+}
+
+// Parses the whole text upfront into a flat list of values, with struct field tables pointing into
+// it, mirroring the layout of persistent savestates.
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+    // Values along with their positions in the text.
+    values: Vec<(TextValue, usize)>,
+    idents: Vec<u8>,
+    structs: Vec<ReadStructInfo>,
+}
+
+impl<'a> Parser<'a> {
+    fn syntax_error(&self, pos: usize) -> ReadError {
+        let line_start = self.text[..pos]
+            .iter()
+            .rposition(|byte| *byte == b'\n')
+            .map_or(0, |i| i + 1);
+        ReadErrorKind::InvalidSyntax {
+            line: self.text[..pos]
+                .iter()
+                .filter(|byte| **byte == b'\n')
+                .count()
+                + 1,
+            column: pos - line_start + 1,
+        }
+        .at(pos as u64)
+    }
+
+    #[inline]
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(byte) = self.peek() {
+            if byte.is_ascii_whitespace() || byte == b',' {
+                self.pos += 1;
+            } else if self.text[self.pos..].starts_with(b"//") {
+                while self.peek().is_some_and(|byte| byte != b'\n') {
+                    self.pos += 1;
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), ReadError> {
+        self.skip_whitespace();
+        if self.peek() != Some(expected) {
+            return Err(self.syntax_error(self.pos));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn word(&mut self) -> &'a str {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|byte| byte.is_ascii_alphanumeric() || b"_.+-".contains(&byte))
+        {
+            self.pos += 1;
+        }
+        // Only ASCII characters were consumed
+        core::str::from_utf8(&self.text[start..self.pos]).unwrap()
+    }
+
+    fn string(&mut self) -> Result<Vec<u8>, ReadError> {
+        self.expect(b'"')?;
+        let mut bytes = Vec::new();
+        loop {
+            let byte = self.peek().ok_or_else(|| self.syntax_error(self.pos))?;
+            self.pos += 1;
+            match byte {
+                b'"' => return Ok(bytes),
+                b'\\' => {
+                    let escape_pos = self.pos - 1;
+                    match self.peek() {
+                        Some(byte @ (b'"' | b'\\')) => {
+                            self.pos += 1;
+                            bytes.push(byte);
+                        }
+                        Some(b'x') => {
+                            let byte = self
+                                .text
+                                .get(self.pos + 1..self.pos + 3)
+                                .and_then(|hex| core::str::from_utf8(hex).ok())
+                                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                                .ok_or_else(|| self.syntax_error(escape_pos))?;
+                            self.pos += 3;
+                            bytes.push(byte);
+                        }
+                        _ => return Err(self.syntax_error(escape_pos)),
+                    }
+                }
+                _ => bytes.push(byte),
+            }
+        }
+    }
+
+    // Parses a non-negative integer inside parentheses, as used by `len(...)` and `version(...)`.
+    fn paren_int(&mut self) -> Result<u64, ReadError> {
+        self.expect(b'(')?;
+        self.skip_whitespace();
+        let start = self.pos;
+        let value = parse_int(self.word()).ok_or_else(|| self.syntax_error(start))?;
+        self.expect(b')')?;
+        Ok(value)
+    }
+
+    fn header(&mut self) -> Result<SaveInfo, ReadError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        if self.word() != "EmuState" {
+            return Err(ReadErrorKind::InvalidMagic.at(start as u64));
+        }
+        self.expect(b'(')?;
+
+        let (mut format, mut emu_id, mut emu_version, mut timestamp) = (None, None, None, None);
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(b')') {
+                self.pos += 1;
+                break;
+            }
+            let key_pos = self.pos;
+            let key = self.word();
+            self.expect(b':')?;
+            self.skip_whitespace();
+            let value_pos = self.pos;
+            if key == "emu_id" {
+                let bytes = self.string()?;
+                emu_id = Some(String::from_utf8(bytes).map_err(|_| self.syntax_error(value_pos))?);
+                continue;
+            }
+            let value = parse_int(self.word()).ok_or_else(|| self.syntax_error(value_pos))?;
+            match key {
+                "format" => format = Some(value),
+                "emu_version" => {
+                    emu_version =
+                        Some(u32::try_from(value).map_err(|_| self.syntax_error(value_pos))?);
+                }
+                "timestamp" => timestamp = Some(value),
+                _ => return Err(self.syntax_error(key_pos)),
+            }
+        }
+
+        match format {
+            Some(TEXT_FORMAT_VERSION) => {}
+            Some(format) => {
+                return Err(
+                    ReadErrorKind::UnsupportedVersion(format.min(u16::MAX as u64) as u16)
+                        .at(start as u64),
+                )
+            }
+            None => return Err(ReadErrorKind::InvalidHeader.at(start as u64)),
+        }
+        match (emu_id, emu_version, timestamp) {
+            (Some(emu_id), Some(emu_version), Some(timestamp)) => Ok(SaveInfo {
+                emu_id,
+                emu_version,
+                timestamp,
+            }),
+            _ => Err(ReadErrorKind::InvalidHeader.at(start as u64)),
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<(), ReadError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        if self.peek() == Some(b'{') {
+            return self.struct_value(depth);
+        }
+
+        let word = self.word();
+        let value = match word {
+            "len" if self.peek() == Some(b'(') => TextValue::ArrayLen(self.paren_int()?),
+            "bytes" if self.peek() == Some(b'(') => {
+                self.pos += 1;
+                let mut bytes = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b')') => break,
+                        Some(high) if high.is_ascii_hexdigit() => {
+                            let byte = self
+                                .text
+                                .get(self.pos..self.pos + 2)
+                                .and_then(|hex| core::str::from_utf8(hex).ok())
+                                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                                .ok_or_else(|| self.syntax_error(self.pos))?;
+                            bytes.push(byte);
+                            self.pos += 2;
+                        }
+                        _ => return Err(self.syntax_error(self.pos)),
+                    }
+                }
+                self.pos += 1;
+                TextValue::Bytes(bytes)
+            }
+            _ => {
+                let (ty, bytes) = parse_raw(word).ok_or_else(|| self.syntax_error(start))?;
+                TextValue::Raw(ty, bytes)
+            }
+        };
+        self.values.push((value, start));
+        Ok(())
+    }
+
+    fn struct_value(&mut self, depth: usize) -> Result<(), ReadError> {
+        if depth >= MAX_DEPTH {
+            return Err(self.syntax_error(self.pos));
+        }
+        let struct_index = self.structs.len();
+        self.structs.push(ReadStructInfo {
+            version: 0,
+            fields: Vec::new(),
+            end: 0,
+            cur_field: 0,
+            active_field: None,
+        });
+        self.values
+            .push((TextValue::Struct(struct_index), self.pos));
+        self.pos += 1;
+
+        let mut version = 0;
+        let mut fields = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let start = self.pos;
+            let ident = match self.peek() {
+                None => return Err(self.syntax_error(start)),
+                Some(b'}') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(b'"') => {
+                    let ident = self.string()?;
+                    self.expect(b':')?;
+                    Some(ident)
+                }
+                Some(_) => {
+                    let word = self.word();
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b':') if !word.is_empty() => {
+                            self.pos += 1;
+                            Some(word.as_bytes().to_vec())
+                        }
+                        Some(b'(') if word == "version" => {
+                            version = u32::try_from(self.paren_int()?)
+                                .map_err(|_| self.syntax_error(start))?;
+                            continue;
+                        }
+                        _ => {
+                            self.pos = start;
+                            None
+                        }
+                    }
+                }
+            };
+
+            match ident {
+                Some(ident) => {
+                    let ident_start = self.idents.len();
+                    self.idents.extend_from_slice(&ident);
+                    fields.push(FieldInfo {
+                        ident_start,
+                        ident_end: self.idents.len(),
+                        pos: self.values.len() as u64,
+                    });
+                }
+                // Values need to belong to a field
+                None if fields.is_empty() => return Err(self.syntax_error(start)),
+                None => self.value(depth + 1)?,
+            }
+        }
+
+        let struct_info = &mut self.structs[struct_index];
+        struct_info.version = version;
+        struct_info.fields = fields;
+        struct_info.end = self.values.len() as u64;
+        Ok(())
+    }
+}
+
+// Parses a non-negative decimal or hexadecimal integer, optionally with `_` separators.
+fn parse_int(word: &str) -> Option<u64> {
+    let word = word.replace('_', "");
+    match word.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => word.parse().ok(),
+    }
+}
+
+// Parses a raw value literal, returning its type and little-endian representation.
+fn parse_raw(word: &str) -> Option<(ValueType, [u8; 16])> {
+    let (value, suffix) = word.rsplit_once('_')?;
+    let kind = match suffix.as_bytes().first()? {
+        b'u' => ValueKind::Unsigned,
+        b'i' => ValueKind::Signed,
+        b'f' => ValueKind::Float,
+        _ => return None,
+    };
+    let bits = suffix[1..].parse::<usize>().ok()?;
+    let ty = ValueType {
+        kind,
+        size: bits / 8,
+    };
+    if bits % 8 != 0 || ValueType::from_tag(ty.to_tag()) != Some(ty) {
+        return None;
+    }
+
+    let value = value.replace('_', "");
+    let (negative, magnitude) = match value.strip_prefix('-') {
+        Some(magnitude) => (true, magnitude),
+        None => (false, value.as_str()),
+    };
+    let hex = magnitude.strip_prefix("0x");
+
+    let mut bytes = [0; 16];
+    if kind == ValueKind::Float && hex.is_none() {
+        if ty.size == 4 {
+            bytes[..4].copy_from_slice(&value.parse::<f32>().ok()?.to_le_bytes());
+        } else {
+            bytes[..8].copy_from_slice(&value.parse::<f64>().ok()?.to_le_bytes());
+        }
+        return Some((ty, bytes));
+    }
+
+    let magnitude = match hex {
+        Some(hex) => u128::from_str_radix(hex, 16).ok()?,
+        None => magnitude.parse::<u128>().ok()?,
+    };
+    let value = if kind == ValueKind::Signed {
+        let limit = 1 << (bits - 1);
+        if negative {
+            (magnitude <= limit).then(|| magnitude.wrapping_neg())?
+        } else {
+            (magnitude < limit).then_some(magnitude)?
+        }
+    } else {
+        // Hexadecimal literals of float types hold their bit patterns
+        (!negative && (bits == 128 || magnitude >> bits == 0)).then_some(magnitude)?
+    };
+    bytes.copy_from_slice(&value.to_le_bytes());
+    Some((ty, bytes))
+}
+
+// Loads the format written by `TextWriteSavestate`.
+pub struct TextReadSavestate {
+    info: SaveInfo,
+    text_len: usize,
+    values: Vec<(TextValue, usize)>,
+    idents: Vec<u8>,
+    parsed_structs: Vec<ReadStructInfo>,
+    pos: usize,
+    structs: Vec<ReadStructInfo>,
+}
+
+impl TextReadSavestate {
+    pub fn new(text: &str) -> Result<Self, ReadError> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            pos: 0,
+            values: Vec::new(),
+            idents: Vec::new(),
+            structs: Vec::new(),
+        };
+        let info = parser.header()?;
+        loop {
+            parser.skip_whitespace();
+            if parser.pos == text.len() {
+                break;
+            }
+            parser.value(0)?;
+        }
+
+        Ok(TextReadSavestate {
+            info,
+            text_len: text.len(),
+            values: parser.values,
+            idents: parser.idents,
+            parsed_structs: parser.structs,
+            pos: 0,
+            structs: Vec::new(),
+        })
+    }
+
+    #[inline]
+    pub fn info(&self) -> &SaveInfo {
+        &self.info
+    }
+
+    // Adds the path of the fields being loaded to errors, along with the position in the text of
+    // the current value.
+    fn add_context(&self, kind: ReadErrorKind) -> ReadError {
+        ReadError {
+            kind,
+            path: field_path(&self.structs, &self.idents),
+            offset: Some(
+                self.values
+                    .get(self.pos)
+                    .map_or(self.text_len, |(_, offset)| *offset) as u64,
+            ),
+        }
+    }
+
+    fn next_value(&mut self) -> Result<&TextValue, ReadError> {
+        let end = self
+            .structs
+            .last()
+            .map_or(self.values.len(), |struct_info| struct_info.end as usize);
+        if self.pos >= end {
+            return Err(self.add_context(ReadErrorKind::UnexpectedValue));
+        }
+        self.pos += 1;
+        Ok(&self.values[self.pos - 1].0)
+    }
+}
+
+impl ReadSavestate for TextReadSavestate {
+    type Error = ReadError;
+
+    const TRANSIENT: bool = false;
+
+    fn invalid_enum(&self) -> Self::Error {
+        self.add_context(ReadErrorKind::InvalidEnum)
+    }
+
+    fn invalid_utf8(&self) -> Self::Error {
+        self.add_context(ReadErrorKind::InvalidUtf8)
+    }
+
+    fn invalid_length(&self, expected: u64, found: u64) -> Self::Error {
+        self.add_context(ReadErrorKind::LengthMismatch { expected, found })
+    }
+
+    fn field_not_found(&self, ident: &[u8]) -> Self::Error {
+        let mut err = self.add_context(ReadErrorKind::FieldNotFound);
+        if !err.path.is_empty() {
+            err.path.push('.');
+        }
+        err.path.push_str(&String::from_utf8_lossy(ident));
+        err
+    }
+
+    fn unsupported_struct_version(&self, version: u32) -> Self::Error {
+        self.add_context(ReadErrorKind::UnsupportedStructVersion(version))
+    }
+
+    fn load_array_len(&mut self) -> Result<usize, Self::Error> {
+        let pos = self.pos;
+        match self.next_value()? {
+            TextValue::ArrayLen(len) => {
+                let len = *len;
+                usize::try_from(len).map_err(|_| self.add_context(ReadErrorKind::SaveTooLarge))
+            }
+            _ => {
+                self.pos = pos;
+                Err(self.add_context(ReadErrorKind::UnexpectedValue))
+            }
+        }
+    }
+
+    fn load_raw<T: MemValue>(&mut self) -> Result<T, Self::Error> {
+        let pos = self.pos;
+        let (ty, bytes) = match self.next_value()? {
+            TextValue::Raw(ty, bytes) => (*ty, *bytes),
+            _ => {
+                self.pos = pos;
+                return Err(self.add_context(ReadErrorKind::UnexpectedValue));
+            }
+        };
+        ty.read_as(&bytes[..ty.size]).ok_or_else(|| {
+            self.pos = pos;
+            self.add_context(ReadErrorKind::TypeMismatch {
+                expected: ValueType::of::<T>(),
+                found: ty,
+            })
+        })
+    }
+
+    fn load_usize(&mut self) -> Result<usize, Self::Error> {
+        let pos = self.pos;
+        usize::try_from(self.load_raw::<u64>()?).map_err(|_| {
+            self.pos = pos;
+            self.add_context(ReadErrorKind::ValueOutOfRange)
+        })
+    }
+
+    fn load_isize(&mut self) -> Result<isize, Self::Error> {
+        let pos = self.pos;
+        isize::try_from(self.load_raw::<i64>()?).map_err(|_| {
+            self.pos = pos;
+            self.add_context(ReadErrorKind::ValueOutOfRange)
+        })
+    }
+
+    fn load_raw_slice<T: MemValue>(&mut self, values: &mut [T]) -> Result<(), Self::Error> {
+        for value in values {
+            *value = self.load_raw()?;
+        }
+        Ok(())
+    }
+
+    fn load_raw_vec<T: MemValue>(
+        &mut self,
+        len: usize,
+        values: &mut Vec<T>,
+    ) -> Result<(), Self::Error> {
+        values.clear();
+        for _ in 0..len {
+            values.push(self.load_raw()?);
+        }
+        Ok(())
+    }
+
+    fn load_bytes(&mut self, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let pos = self.pos;
+        let kind = match self.next_value()? {
+            TextValue::Bytes(value) if value.len() == bytes.len() => {
+                bytes.copy_from_slice(value);
+                return Ok(());
+            }
+            TextValue::Bytes(value) => ReadErrorKind::LengthMismatch {
+                expected: bytes.len() as u64,
+                found: value.len() as u64,
+            },
+            _ => ReadErrorKind::UnexpectedValue,
+        };
+        self.pos = pos;
+        Err(self.add_context(kind))
+    }
+
+    fn check_bytes_len(&mut self, len: usize) -> Result<(), Self::Error> {
+        let pos = self.pos;
+        let kind = match self.next_value()? {
+            TextValue::Bytes(value) if value.len() == len => None,
+            TextValue::Bytes(value) => Some(ReadErrorKind::LengthMismatch {
+                expected: len as u64,
+                found: value.len() as u64,
+            }),
+            _ => Some(ReadErrorKind::UnexpectedValue),
+        };
+        self.pos = pos;
+        match kind {
+            Some(kind) => Err(self.add_context(kind)),
+            None => Ok(()),
+        }
+    }
+
+    fn start_struct(&mut self) -> Result<(), Self::Error> {
+        let pos = self.pos;
+        match self.next_value()? {
+            &TextValue::Struct(i) => {
+                let struct_info = self.parsed_structs[i].clone();
+                self.structs.push(struct_info);
+                Ok(())
+            }
+            _ => {
+                self.pos = pos;
+                Err(self.add_context(ReadErrorKind::UnexpectedValue))
+            }
+        }
+    }
+
+    fn end_struct(&mut self) -> Result<(), Self::Error> {
+        match self.structs.pop() {
+            Some(struct_info) => {
+                self.pos = struct_info.end as usize;
+                Ok(())
+            }
+            None => Err(self.add_context(ReadErrorKind::NoStructPresent)),
+        }
+    }
+
+    fn struct_version(&mut self) -> Result<u32, Self::Error> {
+        self.structs
+            .last()
+            .map(|struct_info| struct_info.version)
+            .ok_or_else(|| self.add_context(ReadErrorKind::NoStructPresent))
+    }
+
+    fn try_start_field_aliased(
+        &mut self,
+        ident: &[u8],
+        aliases: &[&[u8]],
+    ) -> Result<bool, Self::Error> {
+        let Some(cur_struct) = self.structs.last_mut() else {
+            return Err(self.add_context(ReadErrorKind::NoStructPresent));
+        };
+        let Some(pos) = cur_struct.find_field_aliased(&self.idents, ident, aliases) else {
+            return Ok(false);
+        };
+        self.pos = pos as usize;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "EmuState(format: 1, emu_id: \"test\", emu_version: 1, timestamp: 0)";
+
+    fn write(f: impl FnOnce(&mut TextWriteSavestate) -> Result<(), WriteError>) -> String {
+        let mut text = String::new();
+        let mut save = TextWriteSavestate::new(&mut text, &SaveInfo::new("test", 1));
+        f(&mut save).unwrap();
+        save.finish().unwrap();
+        text
+    }
+
+    #[test]
+    fn raw_literals() {
+        let text = write(|save| {
+            save.store_raw(0x1f_u16);
+            save.store_raw(-3_i8);
+            save.store_raw(1.5_f32);
+            save.store_raw(f32::from_bits(0x7FC0_0001));
+            save.store_raw(f64::from_bits(0xFFF0_0000_0000_0001));
+            save.store_raw(u128::MAX);
+            save.store_raw(i64::MIN);
+            Ok(())
+        });
+        assert!(text.contains(
+            "0x1f_u16 -3_i8 1.5_f32 0x7fc00001_f32 0xfff0000000000001_f64 \
+             0xffffffffffffffffffffffffffffffff_u128"
+        ));
+        // Lines get wrapped before exceeding `MAX_LINE_LEN`
+        assert!(text.contains("_u128\n    -9223372036854775808_i64"));
+
+        let mut save = TextReadSavestate::new(&text).unwrap();
+        assert_eq!(save.load_raw::<u16>().unwrap(), 0x1f);
+        assert_eq!(save.load_raw::<i8>().unwrap(), -3);
+        assert_eq!(save.load_raw::<f32>().unwrap(), 1.5);
+        assert_eq!(save.load_raw::<f32>().unwrap().to_bits(), 0x7FC0_0001);
+        assert_eq!(
+            save.load_raw::<f64>().unwrap().to_bits(),
+            0xFFF0_0000_0000_0001
+        );
+        assert_eq!(save.load_raw::<u128>().unwrap(), u128::MAX);
+        assert_eq!(save.load_raw::<i64>().unwrap(), i64::MIN);
+        assert_eq!(
+            save.load_raw::<u8>().unwrap_err().kind,
+            ReadErrorKind::UnexpectedValue
+        );
+    }
+
+    #[test]
+    fn bytes_lengths_and_versions() {
+        let long = (0..100).collect::<Vec<u8>>();
+        let text = write(|save| {
+            save.start_struct()?;
+            save.set_struct_version(3)?;
+            save.start_field(b"short")?;
+            save.store_byte_slice(&[0x00, 0xFF]);
+            save.start_field(b"long")?;
+            save.store_byte_slice(&long);
+            save.start_field(b"values")?;
+            save.store_array_len(2)?;
+            save.store_raw(5_u8);
+            save.store_raw(6_u8);
+            save.end_struct()
+        });
+        assert!(text.contains("version(3)"));
+        assert!(text.contains("short: bytes(00ff)"));
+        assert!(text.contains("values: len(2) 0x5_u8 0x6_u8"));
+
+        let mut save = TextReadSavestate::new(&text).unwrap();
+        save.start_struct().unwrap();
+        assert_eq!(save.struct_version().unwrap(), 3);
+        save.start_field(b"long").unwrap();
+        let mut bytes = [0; 100];
+        save.load_bytes(&mut bytes).unwrap();
+        assert_eq!(bytes[..], long[..]);
+        save.start_field(b"values").unwrap();
+        assert_eq!(save.load_array_len().unwrap(), 2);
+        assert_eq!(save.load_raw::<u8>().unwrap(), 5);
+        assert_eq!(save.load_raw::<u8>().unwrap(), 6);
+        save.start_field(b"short").unwrap();
+        assert_eq!(
+            save.load_bytes(&mut [0; 3]).unwrap_err().kind,
+            ReadErrorKind::LengthMismatch {
+                expected: 3,
+                found: 2
+            }
+        );
+        save.load_bytes(&mut [0; 2]).unwrap();
+        save.end_struct().unwrap();
+    }
+
+    #[test]
+    fn malformed_literals() {
+        for value in [
+            "0x1f",
+            "1f_u16",
+            "0x1f_u17",
+            "0x1f_x16",
+            "_u8",
+            "300_u8",
+            "0x100_u8",
+            "-1_u8",
+            "128_i8",
+            "-129_i8",
+            "1.5_u32",
+            "1.5",
+            "-0x1_f32",
+            "0x1_0000_0000_f32",
+            "bytes(0)",
+            "bytes(zz)",
+            "bytes(00",
+            "len(-1)",
+            "len()",
+            "{ version(0x1_0000_0000) }",
+            "{ a: 0x1_u8 b }",
+            "{ 0x1_u8 }",
+        ] {
+            let err = TextReadSavestate::new(&format!("{HEADER}\n  {value}"))
+                .err()
+                .unwrap_or_else(|| panic!("`{value}` should be rejected"));
+            assert!(
+                matches!(err.kind, ReadErrorKind::InvalidSyntax { line: 2, .. }),
+                "unexpected error for `{value}`: {err}"
+            );
+        }
+
+        let err = TextReadSavestate::new(&format!("{HEADER}\n  300_u8"))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind,
+            ReadErrorKind::InvalidSyntax { line: 2, column: 3 }
+        );
+    }
+}
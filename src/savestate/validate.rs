@@ -0,0 +1,109 @@
+// In debug builds, transient savestates are interleaved with markers describing what was stored,
+// which the reader checks against what's being loaded to catch asymmetric `Storable` and
+// `Loadable` implementations before they corrupt memory. Raw values are preceded by their type tag
+// (see `ValueType`), which never collides with the markers below.
+
+use super::ValueType;
+
+pub(super) const STRUCT_START: u8 = 0xF0;
+pub(super) const STRUCT_END: u8 = 0xF1;
+// Followed by a hash of the field's identifier.
+pub(super) const FIELD: u8 = 0xF2;
+// Followed by the length of the byte array as a `u32`.
+pub(super) const BYTES: u8 = 0xF3;
+pub(super) const ARRAY_LEN: u8 = 0xF4;
+// Followed by the type tag of the values and the length of the slice as a `u32`.
+pub(super) const SLICE: u8 = 0xF5;
+
+// 32-bit FNV-1a.
+pub(super) fn ident_hash(ident: &[u8]) -> u32 {
+    ident.iter().fold(0x811C_9DC5, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+pub(super) fn describe_marker(marker: u8) -> String {
+    match marker {
+        STRUCT_START => "struct start".to_string(),
+        STRUCT_END => "struct end".to_string(),
+        FIELD => "field".to_string(),
+        BYTES => "byte array".to_string(),
+        ARRAY_LEN => "array length".to_string(),
+        SLICE => "raw slice".to_string(),
+        _ => match ValueType::from_tag(marker) {
+            Some(ty) => format!("{ty} value"),
+            None => format!("unknown marker {marker:#04x}"),
+        },
+    }
+}
+
+// Tracks the fields being loaded, to report where a mismatch was found.
+#[derive(Default)]
+pub(super) struct FieldPath(Vec<Option<Vec<u8>>>);
+
+impl FieldPath {
+    pub fn start_struct(&mut self) {
+        self.0.push(None);
+    }
+
+    pub fn end_struct(&mut self) {
+        self.0.pop();
+    }
+
+    pub fn start_field(&mut self, ident: &[u8]) {
+        if let Some(field) = self.0.last_mut() {
+            *field = Some(ident.to_vec());
+        }
+    }
+
+    pub fn mismatch(&self, pos: usize, expected: &str, found: &str) -> ! {
+        let path = self
+            .0
+            .iter()
+            .flatten()
+            .map(|ident| String::from_utf8_lossy(ident))
+            .collect::<Vec<_>>()
+            .join(".");
+        panic!(
+            "transient savestate mismatch at field `{path}`, offset {pos:#x}: expected {expected}, \
+             found {found}"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ReadSavestate, Savestate, TransientReadSavestate, TransientWriteSavestate, WriteSavestate,
+    };
+
+    #[derive(Savestate)]
+    struct VecState {
+        value: Vec<u32>,
+    }
+
+    #[derive(Savestate)]
+    struct VecHolder {
+        state: VecState,
+    }
+
+    #[derive(Savestate)]
+    struct U32State {
+        value: u32,
+    }
+
+    #[derive(Savestate)]
+    struct U32Holder {
+        state: U32State,
+    }
+
+    #[test]
+    #[should_panic(expected = "mismatch at field `state.value`")]
+    fn mismatch_path() {
+        let mut save = Vec::new();
+        let Ok(()) = TransientWriteSavestate::new(&mut save).store(&mut VecHolder {
+            state: VecState { value: vec![1, 2] },
+        });
+        let Ok(_) = unsafe { TransientReadSavestate::new(&save) }.load::<U32Holder>();
+    }
+}
@@ -0,0 +1,136 @@
+use crate::MemValue;
+use core::{fmt, mem::size_of};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueKind {
+    Unsigned,
+    Signed,
+    Float,
+}
+
+// The type of a raw value in a persistent savestate, recorded as a one-byte tag before it since
+// version 2 of the format: the kind in the high nibble and the base-2 logarithm of the size in the
+// low one. Slices of raw values are stored as a single tag with the high bit set, followed by the
+// packed values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ValueType {
+    pub kind: ValueKind,
+    /// Size in bytes; one of 1, 2, 4, 8 or 16.
+    pub size: usize,
+}
+
+impl ValueType {
+    #[inline]
+    pub(super) fn of<T: MemValue>() -> Self {
+        ValueType {
+            kind: if T::FLOAT {
+                ValueKind::Float
+            } else if T::SIGNED {
+                ValueKind::Signed
+            } else {
+                ValueKind::Unsigned
+            },
+            size: size_of::<T>(),
+        }
+    }
+
+    #[inline]
+    pub(super) fn to_tag(self) -> u8 {
+        let kind = match self.kind {
+            ValueKind::Unsigned => 0,
+            ValueKind::Signed => 1,
+            ValueKind::Float => 2,
+        };
+        kind << 4 | self.size.trailing_zeros() as u8
+    }
+
+    #[inline]
+    pub(super) fn to_slice_tag(self) -> u8 {
+        0x80 | self.to_tag()
+    }
+
+    #[inline]
+    pub(super) fn from_slice_tag(tag: u8) -> Option<Self> {
+        if tag & 0x80 == 0 {
+            return None;
+        }
+        Self::from_tag(tag & 0x7F)
+    }
+
+    #[inline]
+    pub(super) fn from_tag(tag: u8) -> Option<Self> {
+        let kind = match tag >> 4 {
+            0 => ValueKind::Unsigned,
+            1 => ValueKind::Signed,
+            2 => ValueKind::Float,
+            _ => return None,
+        };
+        let size_shift = tag & 0xF;
+        let valid = match kind {
+            ValueKind::Float => (2..=3).contains(&size_shift),
+            _ => size_shift <= 4,
+        };
+        valid.then_some(ValueType {
+            kind,
+            size: 1 << size_shift,
+        })
+    }
+
+    /// Whether every value of this type can be converted to `other` without losing information.
+    pub fn widens_to(self, other: Self) -> bool {
+        match (self.kind, other.kind) {
+            (ValueKind::Unsigned, ValueKind::Unsigned)
+            | (ValueKind::Signed, ValueKind::Signed)
+            | (ValueKind::Float, ValueKind::Float) => self.size <= other.size,
+            (ValueKind::Unsigned, ValueKind::Signed) => self.size < other.size,
+            _ => false,
+        }
+    }
+
+    // Reads a little-endian value of this type as a `T`, widening it if needed, or returns `None`
+    // if it can't be converted losslessly; `bytes` must be `self.size` bytes long.
+    pub(super) fn read_as<T: MemValue>(self, bytes: &[u8]) -> Option<T> {
+        let ty = Self::of::<T>();
+        if self == ty {
+            return Some(unsafe { T::read_le(bytes.as_ptr() as *const T) });
+        }
+        if !self.widens_to(ty) {
+            return None;
+        }
+
+        let mut widened = [0; 16];
+        if self.kind == ValueKind::Float {
+            let value = f32::from_le_bytes(bytes.try_into().unwrap()) as f64;
+            widened[..8].copy_from_slice(&value.to_le_bytes());
+        } else {
+            widened[..self.size].copy_from_slice(bytes);
+            if self.kind == ValueKind::Signed && bytes[self.size - 1] & 0x80 != 0 {
+                widened[self.size..ty.size].fill(0xFF);
+            }
+        }
+        Some(unsafe { T::read_le(widened.as_ptr() as *const T) })
+    }
+}
+
+// Converts `len` values copied in bulk to or from a persistent savestate between native and
+// little-endian byte order, in place; `ptr` must be valid for reads and writes of all of them.
+#[inline]
+pub(super) unsafe fn fix_slice_endianness<T: MemValue>(ptr: *mut T, len: usize) {
+    if cfg!(target_endian = "big") {
+        for i in 0..len {
+            let ptr = ptr.add(i);
+            ptr.write_unaligned(T::read_le(ptr));
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let prefix = match self.kind {
+            ValueKind::Unsigned => 'u',
+            ValueKind::Signed => 'i',
+            ValueKind::Float => 'f',
+        };
+        write!(f, "{}{}", prefix, self.size * 8)
+    }
+}
@@ -0,0 +1,87 @@
+// Unsigned LEB128 integers, used by version 2 of the persistent format for lengths and offsets.
+
+pub(super) const MAX_VARINT_LEN: usize = 10;
+
+#[inline]
+pub(super) fn write_varint(save: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        save.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    save.push(value as u8);
+}
+
+// Returns `Ok(None)` if the encoded value doesn't fit in a `u64`, or isn't in its shortest form.
+#[inline]
+pub(super) fn read_varint<E>(
+    mut read_byte: impl FnMut() -> Result<u8, E>,
+) -> Result<Option<u64>, E> {
+    let mut value = 0;
+    for i in 0..MAX_VARINT_LEN {
+        let byte = read_byte()?;
+        let bits = (byte & 0x7F) as u64;
+        if i == MAX_VARINT_LEN - 1 && bits > 1 {
+            return Ok(None);
+        }
+        value |= bits << (i * 7);
+        if byte & 0x80 == 0 {
+            return Ok(if byte == 0 && i != 0 {
+                None
+            } else {
+                Some(value)
+            });
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(encoded: &[u8]) -> Option<u64> {
+        let mut bytes = encoded.iter().copied();
+        let value = read_varint(|| bytes.next().ok_or(())).expect("varint should be complete");
+        assert_eq!(bytes.next(), None);
+        value
+    }
+
+    #[test]
+    fn round_trip() {
+        for (value, encoded) in [
+            (0, &[0x00][..]),
+            (127, &[0x7F]),
+            (128, &[0x80, 0x01]),
+            (300, &[0xAC, 0x02]),
+            (
+                u64::MAX,
+                &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01],
+            ),
+        ] {
+            let mut save = Vec::new();
+            write_varint(&mut save, value);
+            assert_eq!(save, encoded);
+            assert_eq!(decode(encoded), Some(value));
+        }
+    }
+
+    #[test]
+    fn reject_malformed() {
+        // Non-minimal encodings
+        assert_eq!(decode(&[0x80, 0x00]), None);
+        assert_eq!(decode(&[0xFF, 0x80, 0x00]), None);
+        // Values past `u64::MAX`
+        assert_eq!(
+            decode(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x02]),
+            None
+        );
+        // 11-byte encodings
+        assert_eq!(
+            decode(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x81]),
+            None
+        );
+        // Truncated encodings
+        let mut bytes = [0x80_u8].into_iter();
+        assert_eq!(read_varint(|| bytes.next().ok_or(())), Err(()));
+    }
+}
@@ -1,14 +1,42 @@
-use crate::{Bytes, MemValue, OwnedBytesCellPtr};
+#[cfg(debug_assertions)]
+use super::validate;
+use super::{
+    checksum::crc32,
+    compress::compress,
+    header::{Header, FLAG_CHECKSUM, FLAG_COMPRESSED, FORMAT_VERSION},
+    value_type::fix_slice_endianness,
+    varint::write_varint,
+    SaveInfo, ValueType,
+};
+use crate::{
+    BoxedByteSlice, Bytes, DirtyPages, DirtyTracked, MemValue, OwnedByteSliceCellPtr,
+    OwnedBytesCellPtr,
+};
 use core::{
     cell::Cell,
     convert::Infallible,
-    mem::size_of,
+    fmt,
+    mem::{self, size_of, size_of_val},
     ptr,
     simd::{LaneCount, Simd, SimdElement, SupportedLaneCount},
+    slice,
 };
+use std::io;
 
 pub trait Storable {
     fn store<S: WriteSavestate>(&mut self, save: &mut S) -> Result<(), S::Error>;
+
+    // Stores all elements of `slice` in order; overridden by raw values to store them in bulk.
+    #[inline]
+    fn store_slice<S: WriteSavestate>(slice: &mut [Self], save: &mut S) -> Result<(), S::Error>
+    where
+        Self: Sized,
+    {
+        for elem in slice {
+            elem.store(save)?;
+        }
+        Ok(())
+    }
 }
 
 pub trait WriteSavestate: Sized {
@@ -18,138 +46,630 @@ pub trait WriteSavestate: Sized {
 
     fn store_array_len(&mut self, len: usize) -> Result<(), Self::Error>;
     fn store_raw<T: MemValue>(&mut self, value: T);
-    fn store_bytes<const LEN: usize>(&mut self, bytes: &Bytes<LEN>);
+    // Stores `values` without their length, as a single copy where the format allows it.
+    fn store_raw_slice<T: MemValue>(&mut self, values: &[T]);
+    // Stores `bytes` without their length.
+    fn store_byte_slice(&mut self, bytes: &[u8]);
+    #[inline]
+    fn store_bytes<const LEN: usize>(&mut self, bytes: &Bytes<LEN>) {
+        self.store_byte_slice(&bytes[..]);
+    }
+    // Stores `bytes`, possibly only copying the pages marked in `dirty` if the writer is
+    // overwriting a savestate that already holds the others. Transient writers created with
+    // `with_base` clear `dirty` afterwards, as the pages are tracked relative to the last savestate
+    // written by one.
+    #[inline]
+    fn store_bytes_dirty(&mut self, bytes: &[u8], _dirty: &DirtyPages) {
+        self.store_byte_slice(bytes);
+    }
 
     fn start_struct(&mut self) -> Result<(), Self::Error>;
     fn end_struct(&mut self) -> Result<(), Self::Error>;
     fn start_field(&mut self, ident: &'static [u8]) -> Result<(), Self::Error>;
+    // Records the layout version of the current struct, for migrations when loading it.
+    fn set_struct_version(&mut self, version: u32) -> Result<(), Self::Error>;
 
     #[inline]
     fn store<T: Storable>(&mut self, value: &mut T) -> Result<(), Self::Error> {
         value.store(self)
     }
+
+    // Stores the elements of `slice` without its length.
+    #[inline]
+    fn store_slice<T: Storable>(&mut self, slice: &mut [T]) -> Result<(), Self::Error> {
+        T::store_slice(slice, self)
+    }
 }
 
-// Used for fast, unchecked in-memory savestates (i.e. rewinding).
-pub struct TransientWriteSavestate<'a> {
+// Used for fast, unchecked in-memory savestates (i.e. rewinding). When `PRESIZED` is set, `save`'s
+// capacity is known to fit the whole savestate, and writes skip capacity checks.
+pub struct TransientWriteSavestate<'a, const PRESIZED: bool = false> {
     save: &'a mut Vec<u8>,
+    // Position of the next write; data is overwritten in place up to `save`'s length, which is
+    // truncated to this position once the writer is dropped.
+    pos: usize,
+    // Length of the savestate being overwritten, if any, whose bytes past `pos` are still present
+    // and can be kept for clean pages of dirty-tracked buffers.
+    base_len: Option<usize>,
 }
 
 impl<'a> TransientWriteSavestate<'a> {
     pub fn new(save: &'a mut Vec<u8>) -> Self {
-        TransientWriteSavestate { save }
+        TransientWriteSavestate {
+            pos: save.len(),
+            save,
+            base_len: None,
+        }
+    }
+
+    /// Overwrites the savestate in `save`, only copying the pages of dirty-tracked buffers that
+    /// changed since it was written. `save` is truncated to the length of the new savestate once
+    /// the writer is dropped.
+    ///
+    /// # Safety
+    /// `save` must only contain the last savestate written by a `with_base` writer for the same
+    /// value (or, if there's none, any transient savestate of it), and the layout of the value must
+    /// not have changed since then up to its last dirty-tracked buffer (i.e. the lengths of
+    /// dynamically-sized values preceding them must be the same).
+    pub unsafe fn with_base(save: &'a mut Vec<u8>) -> Self {
+        TransientWriteSavestate {
+            base_len: Some(save.len()),
+            save,
+            pos: 0,
+        }
+    }
+
+    /// Returns the size of the transient savestate of `value`, without storing it.
+    pub fn measure<T: Storable>(value: &mut T) -> usize {
+        let mut measurer = TransientSizeMeasurer { len: 0 };
+        let Ok(()) = measurer.store(value);
+        measurer.len
+    }
+
+    /// Stores `value` after reserving space for all of it, as computed by a measuring pass.
+    pub fn store_presized<T: Storable>(&mut self, value: &mut T) {
+        let len = Self::measure(value);
+        self.save
+            .reserve((self.pos + len).saturating_sub(self.save.len()));
+        let Ok(()) = self.store(value);
+    }
+
+    /// Stores `value` after reserving space for all of it, as computed by a measuring pass, without
+    /// checking the buffer's capacity for each value.
+    ///
+    /// # Safety
+    /// `value` must store the same amount of data every time, i.e. its `Storable` implementation
+    /// can't depend on state it modifies while storing.
+    pub unsafe fn store_presized_unchecked<T: Storable>(&mut self, value: &mut T) {
+        let len = Self::measure(value);
+        self.save
+            .reserve((self.pos + len).saturating_sub(self.save.len()));
+        let mut writer = TransientWriteSavestate::<true> {
+            save: self.save,
+            pos: self.pos,
+            base_len: self.base_len,
+        };
+        let Ok(()) = writer.store(value);
+        self.pos = writer.pos;
+        // Dropping the writer would truncate the base's bytes that this one may still need
+        mem::forget(writer);
     }
 }
 
-impl<'a> WriteSavestate for TransientWriteSavestate<'a> {
+impl<'a, const PRESIZED: bool> TransientWriteSavestate<'a, PRESIZED> {
+    // Makes room for `len` bytes at the current position, returning a pointer to them.
+    #[inline]
+    fn reserve(&mut self, len: usize) -> *mut u8 {
+        let additional = (self.pos + len).saturating_sub(self.save.len());
+        if PRESIZED {
+            debug_assert!(self.save.capacity() - self.save.len() >= additional);
+        } else {
+            self.save.reserve(additional);
+        }
+        unsafe { self.save.as_mut_ptr().add(self.pos) }
+    }
+
+    // Moves past `len` bytes written at the current position.
+    #[inline]
+    unsafe fn advance(&mut self, len: usize) {
+        self.pos += len;
+        if self.pos > self.save.len() {
+            self.save.set_len(self.pos);
+        }
+    }
+
+    #[inline]
+    fn push<T: MemValue>(&mut self, value: T) {
+        unsafe {
+            value.write_ne(self.reserve(size_of::<T>()) as *mut T);
+            self.advance(size_of::<T>());
+        }
+    }
+
+    #[inline]
+    fn push_slice<T: MemValue>(&mut self, values: &[T]) {
+        let len = size_of_val(values);
+        unsafe {
+            ptr::copy_nonoverlapping(values.as_ptr() as *const u8, self.reserve(len), len);
+            self.advance(len);
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    #[inline]
+    fn push_bytes_marker(&mut self, len: usize) {
+        self.push(validate::BYTES);
+        self.push(len as u32);
+    }
+}
+
+impl<'a, const PRESIZED: bool> Drop for TransientWriteSavestate<'a, PRESIZED> {
+    fn drop(&mut self) {
+        // Drops whatever is left of a longer base
+        self.save.truncate(self.pos);
+    }
+}
+
+impl<'a, const PRESIZED: bool> WriteSavestate for TransientWriteSavestate<'a, PRESIZED> {
     type Error = Infallible;
 
     const TRANSIENT: bool = true;
 
     #[inline]
     fn store_array_len(&mut self, len: usize) -> Result<(), Self::Error> {
-        self.store_raw(len as u32);
+        #[cfg(debug_assertions)]
+        self.push(validate::ARRAY_LEN);
+        self.push(len as u32);
         Ok(())
     }
 
     #[inline]
     fn store_raw<T: MemValue>(&mut self, value: T) {
-        unsafe {
-            let pos = self.save.len();
-            self.save.reserve(size_of::<T>());
-            value.write_ne(self.save.as_mut_ptr().add(pos) as *mut T);
-            self.save.set_len(self.save.len() + size_of::<T>());
+        #[cfg(debug_assertions)]
+        self.push(ValueType::of::<T>().to_tag());
+        self.push(value);
+    }
+
+    #[inline]
+    fn store_raw_slice<T: MemValue>(&mut self, values: &[T]) {
+        #[cfg(debug_assertions)]
+        {
+            self.push(validate::SLICE);
+            self.push(ValueType::of::<T>().to_tag());
+            self.push(values.len() as u32);
         }
+        self.push_slice(values);
     }
 
     #[inline]
-    fn store_bytes<const LEN: usize>(&mut self, bytes: &Bytes<LEN>) {
-        unsafe {
-            let pos = self.save.len();
-            self.save.reserve(LEN);
-            ptr::copy_nonoverlapping(bytes.as_ptr(), self.save.as_mut_ptr().add(pos), LEN);
-            self.save.set_len(self.save.len() + LEN);
+    fn store_byte_slice(&mut self, bytes: &[u8]) {
+        #[cfg(debug_assertions)]
+        self.push_bytes_marker(bytes.len());
+        self.push_slice(bytes);
+    }
+
+    fn store_bytes_dirty(&mut self, bytes: &[u8], dirty: &DirtyPages) {
+        #[cfg(debug_assertions)]
+        self.push_bytes_marker(bytes.len());
+        let pos = self.pos;
+        let Some(base_len) = self.base_len else {
+            self.push_slice(bytes);
+            return;
+        };
+        if pos + bytes.len() > base_len {
+            self.push_slice(bytes);
+            dirty.clear();
+            return;
         }
+        // Writes only move forward, so the base's bytes from here on haven't been overwritten yet
+        let base = &mut self.save[pos..pos + bytes.len()];
+        for page in dirty.iter_dirty() {
+            let start = page << DirtyPages::PAGE_SHIFT;
+            let end = (start + DirtyPages::PAGE_SIZE).min(bytes.len());
+            base[start..end].copy_from_slice(&bytes[start..end]);
+        }
+        self.pos += bytes.len();
+        dirty.clear();
     }
 
     #[inline]
     fn start_struct(&mut self) -> Result<(), Self::Error> {
+        #[cfg(debug_assertions)]
+        self.push(validate::STRUCT_START);
         Ok(())
     }
     #[inline]
     fn end_struct(&mut self) -> Result<(), Self::Error> {
+        #[cfg(debug_assertions)]
+        self.push(validate::STRUCT_END);
         Ok(())
     }
     #[inline]
     fn start_field(&mut self, _ident: &'static [u8]) -> Result<(), Self::Error> {
+        #[cfg(debug_assertions)]
+        {
+            self.push(validate::FIELD);
+            self.push(validate::ident_hash(_ident));
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn set_struct_version(&mut self, _version: u32) -> Result<(), Self::Error> {
         Ok(())
     }
 }
 
-struct StructInfo {
-    start_pos: u32,
-    fields: Vec<(&'static [u8], u32)>,
+// Computes the size of transient savestates, mirroring `TransientWriteSavestate`'s layout.
+struct TransientSizeMeasurer {
+    len: usize,
+}
+
+impl WriteSavestate for TransientSizeMeasurer {
+    type Error = Infallible;
+
+    const TRANSIENT: bool = true;
+
+    #[inline]
+    fn store_array_len(&mut self, _len: usize) -> Result<(), Self::Error> {
+        self.len += size_of::<u32>();
+        #[cfg(debug_assertions)]
+        {
+            self.len += 1;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn store_raw<T: MemValue>(&mut self, _value: T) {
+        self.len += size_of::<T>();
+        #[cfg(debug_assertions)]
+        {
+            self.len += 1;
+        }
+    }
+
+    #[inline]
+    fn store_raw_slice<T: MemValue>(&mut self, values: &[T]) {
+        self.len += size_of_val(values);
+        #[cfg(debug_assertions)]
+        {
+            self.len += 2 + size_of::<u32>();
+        }
+    }
+
+    #[inline]
+    fn store_byte_slice(&mut self, bytes: &[u8]) {
+        self.len += bytes.len();
+        #[cfg(debug_assertions)]
+        {
+            self.len += 1 + size_of::<u32>();
+        }
+    }
+
+    #[inline]
+    fn start_struct(&mut self) -> Result<(), Self::Error> {
+        #[cfg(debug_assertions)]
+        {
+            self.len += 1;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn end_struct(&mut self) -> Result<(), Self::Error> {
+        #[cfg(debug_assertions)]
+        {
+            self.len += 1;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn start_field(&mut self, _ident: &'static [u8]) -> Result<(), Self::Error> {
+        #[cfg(debug_assertions)]
+        {
+            self.len += 1 + size_of::<u32>();
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn set_struct_version(&mut self, _version: u32) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+pub(super) struct StructInfo {
+    pub start_pos: u64,
+    pub version: u32,
+    pub fields: Vec<(&'static [u8], u64)>,
+}
+
+impl StructInfo {
+    // Field positions are stored relative to the start of the struct, to keep them short.
+    pub(super) fn write_field_table(&self, save: &mut Vec<u8>) {
+        write_varint(save, self.version as u64);
+        write_varint(save, self.fields.len() as u64);
+        for (ident, pos) in &self.fields {
+            save.extend_from_slice(ident);
+            save.push(0);
+            write_varint(save, pos - self.start_pos);
+        }
+    }
+}
+
+// Returns the dot-separated identifiers of the fields currently being stored, for error reporting.
+pub(super) fn field_path(structs: &[StructInfo]) -> String {
+    let mut path = String::new();
+    for (ident, _) in structs
+        .iter()
+        .filter_map(|struct_info| struct_info.fields.last())
+    {
+        if !path.is_empty() {
+            path.push('.');
+        }
+        path.push_str(&String::from_utf8_lossy(ident));
+    }
+    path
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WriteOptions {
+    pub checksum: bool,
+    /// Compression level, from [`MIN_COMPRESSION_LEVEL`] (fastest) to [`MAX_COMPRESSION_LEVEL`]
+    /// (smallest), or `None` to store the body uncompressed.
+    ///
+    /// [`MIN_COMPRESSION_LEVEL`]: crate::MIN_COMPRESSION_LEVEL
+    /// [`MAX_COMPRESSION_LEVEL`]: crate::MAX_COMPRESSION_LEVEL
+    pub compression: Option<u8>,
 }
 
 pub struct PersistentWriteSavestate<'a> {
+    options: WriteOptions,
+    header_start: usize,
+    encoder: PersistentEncoder<VecBody<'a>>,
+}
+
+impl<'a> PersistentWriteSavestate<'a> {
+    #[inline]
+    pub fn new(
+        save: &'a mut Vec<u8>,
+        info: &SaveInfo,
+        options: WriteOptions,
+    ) -> Result<Self, WriteError> {
+        let header_start = save.len();
+        let mut flags = 0;
+        if options.checksum {
+            flags |= FLAG_CHECKSUM;
+        }
+        if options.compression.is_some() {
+            flags |= FLAG_COMPRESSED;
+        }
+        Header {
+            format_version: FORMAT_VERSION,
+            flags,
+            info: info.clone(),
+        }
+        .write(save)?;
+        let body_start = save.len();
+        Ok(PersistentWriteSavestate {
+            options,
+            header_start,
+            encoder: PersistentEncoder::new(VecBody { save, body_start }),
+        })
+    }
+
+    // Must be called once all values have been stored.
+    pub fn finish(self) -> Result<(), WriteError> {
+        self.encoder.check_finished()?;
+        let VecBody { save, body_start } = self.encoder.sink;
+        if let Some(level) = self.options.compression {
+            let body = save.split_off(body_start);
+            save.extend_from_slice(&(body.len() as u64).to_le_bytes());
+            compress(&body, level, save);
+        }
+        if self.options.checksum {
+            let checksum = crc32(&save[self.header_start..]);
+            save.extend_from_slice(&checksum.to_le_bytes());
+        }
+        Ok(())
+    }
+}
+
+// Body of a persistent savestate being appended to a vector.
+struct VecBody<'a> {
     save: &'a mut Vec<u8>,
+    body_start: usize,
+}
+
+impl<'a> BodySink for VecBody<'a> {
+    // Offsets are relative to the start of the body, right after the header.
+    #[inline]
+    fn body_pos(&self) -> u64 {
+        (self.save.len() - self.body_start) as u64
+    }
+
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        self.save.extend_from_slice(bytes);
+    }
+
+    #[inline]
+    fn write_varint(&mut self, value: u64) {
+        write_varint(self.save, value);
+    }
+
+    #[inline]
+    fn patch(&mut self, pos: u64, bytes: [u8; 8]) -> Result<(), WriteErrorKind> {
+        let pos = self.body_start + pos as usize;
+        self.save[pos..pos + bytes.len()].copy_from_slice(&bytes);
+        Ok(())
+    }
+}
+
+// Destination of a persistent savestate body, written by `PersistentEncoder`.
+pub(super) trait BodySink {
+    fn body_pos(&self) -> u64;
+    fn write(&mut self, bytes: &[u8]);
+    // Writes large byte arrays, which don't need to go through any intermediate buffer.
+    #[inline]
+    fn write_bulk(&mut self, bytes: &[u8]) {
+        self.write(bytes);
+    }
+    fn write_varint(&mut self, value: u64);
+    // Overwrites the zeroed offset placeholder at `pos`.
+    fn patch(&mut self, pos: u64, bytes: [u8; 8]) -> Result<(), WriteErrorKind>;
+    // Returns the first error that occurred while writing, if any; writes themselves are
+    // infallible so raw values can be stored without checks.
+    #[inline]
+    fn check_error(&self) -> Result<(), WriteErrorKind> {
+        Ok(())
+    }
+}
+
+// Stores values in the persistent format; shared by the writers of persistent savestates held in
+// memory and written to streams.
+pub(super) struct PersistentEncoder<S: BodySink> {
+    pub sink: S,
     structs: Vec<StructInfo>,
 }
 
-impl<'a> PersistentWriteSavestate<'a> {
+impl<S: BodySink> PersistentEncoder<S> {
     #[inline]
-    pub fn new(save: &'a mut Vec<u8>) -> Self {
-        PersistentWriteSavestate {
-            save,
+    pub fn new(sink: S) -> Self {
+        PersistentEncoder {
+            sink,
             structs: Vec::new(),
         }
     }
+
+    pub fn error(&self, kind: WriteErrorKind) -> WriteError {
+        WriteError {
+            kind,
+            path: field_path(&self.structs),
+            offset: Some(self.sink.body_pos()),
+        }
+    }
+
+    #[inline]
+    pub fn check_error(&self) -> Result<(), WriteError> {
+        self.sink.check_error().map_err(|kind| self.error(kind))
+    }
+
+    // Checks that all values were stored successfully, and no struct was left unfinished.
+    pub fn check_finished(&self) -> Result<(), WriteError> {
+        if !self.structs.is_empty() {
+            return Err(self.error(WriteErrorKind::UnfinishedStruct));
+        }
+        self.check_error()
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
-pub enum WriteError {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriteErrorKind {
     NoStructPresent,
-    TooManyFields,
-    SaveTooLarge,
+    EmuIdTooLong,
+    UnfinishedStruct,
+    UnsupportedCompression,
+    Io(io::ErrorKind),
+}
+
+impl fmt::Display for WriteErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WriteErrorKind::NoStructPresent => f.write_str("no struct is being stored"),
+            WriteErrorKind::EmuIdTooLong => f.write_str("emulator ID too long"),
+            WriteErrorKind::UnfinishedStruct => f.write_str("unfinished struct"),
+            WriteErrorKind::UnsupportedCompression => {
+                f.write_str("compressed savestates can't be streamed")
+            }
+            WriteErrorKind::Io(kind) => write!(f, "I/O error: {kind}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WriteError {
+    pub kind: WriteErrorKind,
+    /// Dot-separated names of the fields being stored when the error occurred, i.e.
+    /// `gpu.engine_3d.vertex_ram`; empty outside of struct fields.
+    pub path: String,
+    /// Position inside the savestate body at which the error occurred, if any.
+    pub offset: Option<u64>,
 }
 
-impl<'a> WriteSavestate for PersistentWriteSavestate<'a> {
+impl From<WriteErrorKind> for WriteError {
+    #[inline]
+    fn from(kind: WriteErrorKind) -> Self {
+        WriteError {
+            kind,
+            path: String::new(),
+            offset: None,
+        }
+    }
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        match (self.path.is_empty(), self.offset) {
+            (true, None) => Ok(()),
+            (true, Some(offset)) => write!(f, " (offset {offset:#x})"),
+            (false, None) => write!(f, " (field `{}`)", self.path),
+            (false, Some(offset)) => write!(f, " (field `{}`, offset {offset:#x})", self.path),
+        }
+    }
+}
+
+impl std::error::Error for WriteError {}
+
+impl<S: BodySink> WriteSavestate for PersistentEncoder<S> {
     type Error = WriteError;
 
     const TRANSIENT: bool = false;
 
     #[inline]
     fn store_array_len(&mut self, len: usize) -> Result<(), Self::Error> {
-        self.store_raw(u32::try_from(len).map_err(|_| WriteError::TooManyFields)?);
-        Ok(())
+        self.sink.write_varint(len as u64);
+        self.check_error()
     }
 
     #[inline]
     fn store_raw<T: MemValue>(&mut self, value: T) {
-        unsafe {
-            let pos = self.save.len();
-            self.save.reserve(size_of::<T>());
-            value.write_le(self.save.as_mut_ptr().add(pos) as *mut T);
-            self.save.set_len(self.save.len() + size_of::<T>());
-        }
+        let mut bytes = [0; 17];
+        bytes[0] = ValueType::of::<T>().to_tag();
+        unsafe { value.write_le(bytes.as_mut_ptr().add(1) as *mut T) };
+        self.sink.write(&bytes[..1 + size_of::<T>()]);
     }
 
     #[inline]
-    fn store_bytes<const LEN: usize>(&mut self, bytes: &Bytes<LEN>) {
-        unsafe {
-            let pos = self.save.len();
-            self.save.reserve(LEN);
-            ptr::copy_nonoverlapping(bytes.as_ptr(), self.save.as_mut_ptr().add(pos), LEN);
-            self.save.set_len(self.save.len() + LEN);
+    fn store_raw_slice<T: MemValue>(&mut self, values: &[T]) {
+        self.sink.write(&[ValueType::of::<T>().to_slice_tag()]);
+        let bytes =
+            unsafe { slice::from_raw_parts(values.as_ptr() as *const u8, size_of_val(values)) };
+        if cfg!(target_endian = "little") {
+            self.sink.write_bulk(bytes);
+        } else {
+            let mut bytes = bytes.to_vec();
+            unsafe { fix_slice_endianness(bytes.as_mut_ptr() as *mut T, values.len()) };
+            self.sink.write_bulk(&bytes);
         }
     }
 
+    #[inline]
+    fn store_byte_slice(&mut self, bytes: &[u8]) {
+        self.sink.write_bulk(bytes);
+    }
+
     #[inline]
     fn start_struct(&mut self) -> Result<(), Self::Error> {
-        let start_pos = u32::try_from(self.save.len()).map_err(|_| WriteError::SaveTooLarge)?;
-        self.save.extend_from_slice(&[0; 4]);
+        self.check_error()?;
+        let start_pos = self.sink.body_pos();
+        self.sink.write(&[0; 8]);
         self.structs.push(StructInfo {
             start_pos,
+            version: 0,
             fields: Vec::new(),
         });
 
@@ -158,58 +678,110 @@ impl<'a> WriteSavestate for PersistentWriteSavestate<'a> {
 
     #[inline]
     fn end_struct(&mut self) -> Result<(), Self::Error> {
-        let cur_struct = self.structs.pop().ok_or(WriteError::NoStructPresent)?;
+        let Some(cur_struct) = self.structs.pop() else {
+            return Err(self.error(WriteErrorKind::NoStructPresent));
+        };
 
-        let field_info_pos =
-            u32::try_from(self.save.len()).map_err(|_| WriteError::SaveTooLarge)?;
-        unsafe {
-            field_info_pos
-                .write_le(self.save.as_mut_ptr().add(cur_struct.start_pos as usize) as *mut u32);
-        }
-
-        self.save
-            .push(u8::try_from(cur_struct.fields.len()).map_err(|_| WriteError::TooManyFields)?);
-
-        for (ident, pos) in cur_struct.fields {
-            self.save.extend_from_slice(ident);
-            self.save.push(0);
-            self.store_raw(pos);
-        }
+        let field_info_pos = self.sink.body_pos();
+        self.sink
+            .patch(cur_struct.start_pos, field_info_pos.to_le_bytes())
+            .map_err(|kind| self.error(kind))?;
 
-        Ok(())
+        let mut field_table = Vec::new();
+        cur_struct.write_field_table(&mut field_table);
+        self.sink.write(&field_table);
+        self.check_error()
     }
 
     #[inline]
     fn start_field(&mut self, ident: &'static [u8]) -> Result<(), Self::Error> {
-        let cur_struct = self.structs.last_mut().ok_or(WriteError::NoStructPresent)?;
-
-        let pos = u32::try_from(self.save.len()).map_err(|_| WriteError::SaveTooLarge)?;
+        let pos = self.sink.body_pos();
+        let Some(cur_struct) = self.structs.last_mut() else {
+            return Err(self.error(WriteErrorKind::NoStructPresent));
+        };
         cur_struct.fields.push((ident, pos));
 
+        self.check_error()
+    }
+
+    #[inline]
+    fn set_struct_version(&mut self, version: u32) -> Result<(), Self::Error> {
+        let Some(cur_struct) = self.structs.last_mut() else {
+            return Err(self.error(WriteErrorKind::NoStructPresent));
+        };
+        cur_struct.version = version;
         Ok(())
     }
 }
 
-macro_rules! impl_storable_raw {
-    () => {};
+// Implements `WriteSavestate` for a writer by forwarding to its `encoder` field.
+macro_rules! forward_write_savestate {
+    ([$($generics: tt)*] $ty: ty) => {
+        impl<$($generics)*> WriteSavestate for $ty {
+            type Error = WriteError;
+
+            const TRANSIENT: bool = false;
 
-    ($ty: ty as bits $(, $($others: tt)*)?) => {
-        impl Storable for $ty {
             #[inline]
-            fn store<S: WriteSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
-                save.store_raw(self.to_bits());
-                Ok(())
+            fn store_array_len(&mut self, len: usize) -> Result<(), Self::Error> {
+                self.encoder.store_array_len(len)
             }
-        }
 
-        impl_storable_raw!($($($others)*)*);
+            #[inline]
+            fn store_raw<T: MemValue>(&mut self, value: T) {
+                self.encoder.store_raw(value);
+            }
+
+            #[inline]
+            fn store_raw_slice<T: MemValue>(&mut self, values: &[T]) {
+                self.encoder.store_raw_slice(values);
+            }
+
+            #[inline]
+            fn store_byte_slice(&mut self, bytes: &[u8]) {
+                self.encoder.store_byte_slice(bytes);
+            }
+
+            #[inline]
+            fn start_struct(&mut self) -> Result<(), Self::Error> {
+                self.encoder.start_struct()
+            }
+
+            #[inline]
+            fn end_struct(&mut self) -> Result<(), Self::Error> {
+                self.encoder.end_struct()
+            }
+
+            #[inline]
+            fn start_field(&mut self, ident: &'static [u8]) -> Result<(), Self::Error> {
+                self.encoder.start_field(ident)
+            }
+
+            #[inline]
+            fn set_struct_version(&mut self, version: u32) -> Result<(), Self::Error> {
+                self.encoder.set_struct_version(version)
+            }
+        }
     };
+}
+pub(super) use forward_write_savestate;
+
+forward_write_savestate!(['a] PersistentWriteSavestate<'a>);
 
+macro_rules! impl_storable_raw {
+    () => {};
+
+    // Stored natively in transient savestates, and converted to `$conv_ty` in others to be
+    // portable across targets.
     ($ty: ty as $conv_ty: ty $(, $($others: tt)*)?) => {
         impl Storable for $ty {
             #[inline]
             fn store<S: WriteSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
-                save.store_raw(*self as $conv_ty);
+                if S::TRANSIENT {
+                    save.store_raw(*self);
+                } else {
+                    save.store_raw(*self as $conv_ty);
+                }
                 Ok(())
             }
         }
@@ -224,6 +796,15 @@ macro_rules! impl_storable_raw {
                 save.store_raw(*self);
                 Ok(())
             }
+
+            #[inline]
+            fn store_slice<S: WriteSavestate>(
+                slice: &mut [Self],
+                save: &mut S,
+            ) -> Result<(), S::Error> {
+                save.store_raw_slice(slice);
+                Ok(())
+            }
         }
 
         impl_storable_raw!($($($others)*)*);
@@ -232,9 +813,9 @@ macro_rules! impl_storable_raw {
 
 #[rustfmt::skip]
 impl_storable_raw!(
-    u8, u16, u32, u64, u128, usize as u32,
-    i8, i16, i32, i64, i128, isize as i32,
-    f32 as bits, f64 as bits
+    u8, u16, u32, u64, u128, usize as u64,
+    i8, i16, i32, i64, i128, isize as i64,
+    f32, f64
 );
 
 macro_rules! impl_storable_tuples {
@@ -283,9 +864,26 @@ where
     #[inline]
     fn store<S: WriteSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
         save.store_array_len(self.len())?;
-        for elem in self {
-            elem.store(save)?;
-        }
+        save.store_slice(self)
+    }
+}
+
+impl<T> Storable for Box<[T]>
+where
+    T: Storable,
+{
+    #[inline]
+    fn store<S: WriteSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
+        save.store_array_len(self.len())?;
+        save.store_slice(self)
+    }
+}
+
+impl Storable for String {
+    #[inline]
+    fn store<S: WriteSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
+        save.store_array_len(self.len())?;
+        save.store_byte_slice(self.as_bytes());
         Ok(())
     }
 }
@@ -296,10 +894,7 @@ where
 {
     #[inline]
     fn store<S: WriteSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
-        for elem in self {
-            elem.store(save)?;
-        }
-        Ok(())
+        save.store_slice(self)
     }
 }
 
@@ -310,10 +905,7 @@ where
 {
     #[inline]
     fn store<S: WriteSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
-        for elem in self.as_mut_array() {
-            elem.store(save)?;
-        }
-        Ok(())
+        save.store_slice(self.as_mut_array())
     }
 }
 
@@ -333,6 +925,58 @@ impl<const LEN: usize> Storable for OwnedBytesCellPtr<LEN> {
     }
 }
 
+impl<const LEN: usize> Storable for DirtyTracked<Bytes<LEN>> {
+    #[inline]
+    fn store<S: WriteSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
+        save.store_bytes_dirty(&self.inner()[..], self.dirty_pages());
+        Ok(())
+    }
+}
+
+impl<const LEN: usize> Storable for DirtyTracked<OwnedBytesCellPtr<LEN>> {
+    #[inline]
+    fn store<S: WriteSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
+        save.store_bytes_dirty(unsafe { self.inner().as_arr() }, self.dirty_pages());
+        Ok(())
+    }
+}
+
+impl Storable for BoxedByteSlice {
+    #[inline]
+    fn store<S: WriteSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
+        save.store_array_len(self.len())?;
+        save.store_byte_slice(self);
+        Ok(())
+    }
+}
+
+impl Storable for OwnedByteSliceCellPtr {
+    #[inline]
+    fn store<S: WriteSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
+        save.store_array_len(self.len())?;
+        save.store_byte_slice(unsafe { self.as_slice() });
+        Ok(())
+    }
+}
+
+impl Storable for DirtyTracked<BoxedByteSlice> {
+    #[inline]
+    fn store<S: WriteSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
+        save.store_array_len(self.len())?;
+        save.store_bytes_dirty(self.inner(), self.dirty_pages());
+        Ok(())
+    }
+}
+
+impl Storable for DirtyTracked<OwnedByteSliceCellPtr> {
+    #[inline]
+    fn store<S: WriteSavestate>(&mut self, save: &mut S) -> Result<(), S::Error> {
+        save.store_array_len(self.len())?;
+        save.store_bytes_dirty(unsafe { self.inner().as_slice() }, self.dirty_pages());
+        Ok(())
+    }
+}
+
 impl<T> Storable for Box<T>
 where
     T: Storable,
@@ -389,8 +1033,121 @@ pub fn store_slice<S: WriteSavestate, T: Storable>(
     slice: &mut [T],
     save: &mut S,
 ) -> Result<(), S::Error> {
-    for elem in slice {
-        save.store(elem)?;
+    save.store_slice(slice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ByteMutSlice, ByteMutSliceOwnedPtr, Savestate};
+
+    #[derive(Savestate)]
+    struct Machine {
+        regs: [u32; 4],
+        ram: DirtyTracked<BoxedByteSlice>,
+        vram: DirtyTracked<OwnedBytesCellPtr<0x3000>>,
+        frame: u64,
+    }
+
+    fn full_save(machine: &mut Machine) -> Vec<u8> {
+        let mut save = Vec::new();
+        let Ok(()) = TransientWriteSavestate::new(&mut save).store(machine);
+        save
+    }
+
+    fn save_with_base(machine: &mut Machine, save: &mut Vec<u8>) {
+        let Ok(()) = unsafe { TransientWriteSavestate::with_base(save) }.store(machine);
+        assert_eq!(machine.ram.dirty_pages().iter_dirty().count(), 0);
+        assert_eq!(machine.vram.dirty_pages().iter_dirty().count(), 0);
+    }
+
+    #[derive(Savestate)]
+    struct Dynamic {
+        values: Vec<u16>,
+        nested: Vec<Vec<u8>>,
+        name: String,
+        boxed: Box<[u64]>,
+        bytes: BoxedByteSlice,
+        cells: OwnedByteSliceCellPtr,
+        flag: bool,
+    }
+
+    #[test]
+    fn measure() {
+        let mut machine = Machine {
+            regs: [1, 2, 3, 4],
+            ram: DirtyTracked::new(BoxedByteSlice::new_zeroed(100)),
+            vram: DirtyTracked::new(OwnedBytesCellPtr::new_zeroed()),
+            frame: 5,
+        };
+        let mut dynamic = Dynamic {
+            values: (0..1000).collect(),
+            nested: vec![vec![], vec![1, 2, 3]],
+            name: "measured".to_string(),
+            boxed: vec![7; 3].into_boxed_slice(),
+            bytes: BoxedByteSlice::new_zeroed(33),
+            cells: OwnedByteSliceCellPtr::new_zeroed(0),
+            flag: true,
+        };
+        let full = full_save(&mut machine);
+        assert_eq!(TransientWriteSavestate::measure(&mut machine), full.len());
+
+        let mut save = Vec::new();
+        let Ok(()) = TransientWriteSavestate::new(&mut save).store(&mut dynamic);
+        assert_eq!(TransientWriteSavestate::measure(&mut dynamic), save.len());
+
+        let mut presized = Vec::new();
+        TransientWriteSavestate::new(&mut presized).store_presized(&mut dynamic);
+        assert_eq!(presized, save);
+        presized.clear();
+        unsafe {
+            TransientWriteSavestate::new(&mut presized).store_presized_unchecked(&mut dynamic)
+        };
+        assert_eq!(presized, save);
+    }
+
+    #[test]
+    fn dirty_pages_with_base() {
+        let mut machine = Machine {
+            regs: [0; 4],
+            ram: DirtyTracked::new(BoxedByteSlice::new_zeroed(0x4800)),
+            vram: DirtyTracked::new(OwnedBytesCellPtr::new_zeroed()),
+            frame: 0,
+        };
+        let mut base = Vec::new();
+        save_with_base(&mut machine, &mut base);
+        assert_eq!(base, full_save(&mut machine));
+
+        for frame in 1..=4 {
+            machine.frame = frame;
+            machine.regs[frame as usize % 4] = frame as u32;
+            machine.ram.write(0x1000 * frame as usize, frame as u8);
+            machine.vram.write(0x2FFF, frame as u8);
+            match frame {
+                2 => machine.ram.inner_mut()[0x4000] = 0xFF,
+                3 => {
+                    unsafe { *machine.vram.as_ptr().cast_mut() = 0xFF };
+                    machine.vram.mark_dirty(0, 1);
+                }
+                _ => {}
+            }
+            // Saves not made against a base must not affect which pages are dirty
+            let full = full_save(&mut machine);
+            save_with_base(&mut machine, &mut base);
+            assert_eq!(base, full, "frame {frame}");
+        }
+
+        // Whatever is left of a longer base gets dropped
+        machine.ram.write(0, 0xAA);
+        base.extend_from_slice(&[0xAA; 16]);
+        save_with_base(&mut machine, &mut base);
+        assert_eq!(base, full_save(&mut machine));
+
+        machine.ram.write(0, 0xBB);
+        base.extend_from_slice(&[0xBB; 16]);
+        unsafe {
+            TransientWriteSavestate::with_base(&mut base).store_presized_unchecked(&mut machine)
+        };
+        assert_eq!(base, full_save(&mut machine));
     }
-    Ok(())
 }